@@ -0,0 +1,142 @@
+use std::path::PathBuf;
+
+use crate::log::Level;
+
+pub const USAGE: &str = "Usage:
+  d_switch [--config <file.toml>] [--log-level error|info|debug] [--verbose] [--dry-run]
+  d_switch goto <desktop number>
+  d_switch reload";
+
+/// What the command line asks for.
+pub enum Command {
+    /// Hand a 1-based desktop number to the running instance.
+    Goto(u32),
+    /// Have the running instance re-read its config file.
+    Reload,
+    Run(RunOptions),
+}
+
+/// Overrides for the resident instance; `None` keeps the usual behaviour.
+#[derive(Debug, Default)]
+pub struct RunOptions {
+    pub config_path: Option<PathBuf>,
+    pub log_level: Option<Level>,
+    /// Log what hotkeys, menu entries and rules would do instead of doing it.
+    pub dry_run: bool,
+}
+
+/// Parse the arguments after the program name. The error says what was wrong.
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    if let [verb, number] = args
+        && verb == "goto"
+    {
+        return match number.parse::<u32>() {
+            Ok(number @ 1..) => Ok(Command::Goto(number)),
+            _ => Err(format!("not a desktop number: {}", number)),
+        };
+    }
+
+    if let [verb] = args
+        && verb == "reload"
+    {
+        return Ok(Command::Reload);
+    }
+
+    let mut options = RunOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => {
+                let path = args.next().ok_or("--config needs a file")?;
+                options.config_path = Some(PathBuf::from(path));
+            }
+            "--log-level" => {
+                let name = args.next().ok_or("--log-level needs a level")?;
+                let level = Level::parse(name).ok_or(format!("unknown log level: {}", name))?;
+                options.log_level = Some(level);
+            }
+            "--verbose" => options.log_level = Some(Level::Debug),
+            "--dry-run" => options.dry_run = true,
+            other => return Err(format!("unknown argument: {}", other)),
+        }
+    }
+    Ok(Command::Run(options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Command, String> {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        parse(&args)
+    }
+
+    fn run_options(args: &[&str]) -> RunOptions {
+        match parse_args(args) {
+            Ok(Command::Run(options)) => options,
+            _ => panic!("{:?} did not parse as a run", args),
+        }
+    }
+
+    #[test]
+    fn goto_takes_a_desktop_number() {
+        assert!(matches!(parse_args(&["goto", "3"]), Ok(Command::Goto(3))));
+    }
+
+    #[test]
+    fn goto_without_a_valid_number_is_an_error() {
+        assert!(parse_args(&["goto"]).is_err());
+        assert!(parse_args(&["goto", "0"]).is_err());
+        assert!(parse_args(&["goto", "three"]).is_err());
+        assert!(parse_args(&["goto", "-1"]).is_err());
+    }
+
+    #[test]
+    fn reload_stands_alone() {
+        assert!(matches!(parse_args(&["reload"]), Ok(Command::Reload)));
+        assert!(parse_args(&["reload", "now"]).is_err());
+    }
+
+    #[test]
+    fn no_arguments_run_with_the_defaults() {
+        let options = run_options(&[]);
+        assert_eq!(options.config_path, None);
+        assert_eq!(options.log_level, None);
+        assert!(!options.dry_run);
+    }
+
+    #[test]
+    fn config_takes_a_path() {
+        let options = run_options(&["--config", "C:\\d_switch.toml"]);
+        assert_eq!(
+            options.config_path,
+            Some(PathBuf::from("C:\\d_switch.toml"))
+        );
+        assert!(parse_args(&["--config"]).is_err());
+    }
+
+    #[test]
+    fn log_level_and_verbose() {
+        assert_eq!(
+            run_options(&["--log-level", "ERROR"]).log_level,
+            Some(Level::Error)
+        );
+        assert_eq!(run_options(&["--verbose"]).log_level, Some(Level::Debug));
+        assert!(parse_args(&["--log-level"]).is_err());
+        assert!(parse_args(&["--log-level", "loud"]).is_err());
+    }
+
+    #[test]
+    fn dry_run_combines_with_other_options() {
+        let options = run_options(&["--dry-run", "--verbose"]);
+        assert!(options.dry_run);
+        assert_eq!(options.log_level, Some(Level::Debug));
+    }
+
+    #[test]
+    fn unknown_arguments_are_errors() {
+        assert!(parse_args(&["--quiet"]).is_err());
+        assert!(parse_args(&["switch", "3"]).is_err());
+    }
+}
@@ -0,0 +1,536 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::log::Level;
+
+/// What a binding does with its `desktop`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Switch to the desktop.
+    #[default]
+    Switch,
+    /// Send the foreground window to the desktop without following it.
+    Move,
+    /// Send the foreground window to the desktop and switch there with it.
+    MoveFollow,
+    /// Switch to the desktop after the current one (`desktop` is ignored).
+    Next,
+    /// Switch to the desktop before the current one (`desktop` is ignored).
+    Previous,
+    /// Send the foreground window to the next desktop (`desktop` is ignored).
+    MoveNext,
+    /// Send the foreground window to the previous desktop (`desktop` is ignored).
+    MovePrevious,
+    /// Switch to the first desktop (`desktop` is ignored).
+    First,
+    /// Switch to the last desktop (`desktop` is ignored).
+    Last,
+    /// Send the foreground window to the first desktop (`desktop` is ignored).
+    MoveFirst,
+    /// Send the foreground window to the last desktop (`desktop` is ignored).
+    MoveLast,
+    /// Switch back to the desktop active before the last switch (`desktop` is ignored).
+    ToggleLast,
+    /// Go back in the desktop history (`desktop` is ignored).
+    HistoryBack,
+    /// Go forward in the desktop history (`desktop` is ignored).
+    HistoryForward,
+    /// Create a new desktop (`desktop` is ignored).
+    NewDesktop,
+    /// Turn every other hotkey off, or back on (`desktop` is ignored).
+    TogglePause,
+    /// Pin the foreground window to all desktops, or unpin it (`desktop` is ignored).
+    TogglePinWindow,
+    /// Pin or unpin every window of the foreground window's app (`desktop` is ignored).
+    TogglePinApp,
+    /// Create a desktop, send the foreground window there and follow it
+    /// (`desktop` is ignored).
+    MoveToNewDesktop,
+    /// Type a desktop number, then Enter (or wait) to switch there; Esc cancels
+    /// (`desktop` is ignored).
+    GotoNumber,
+    /// Show a numbered grid of all desktops and switch to the one picked by
+    /// number key or click (`desktop` is ignored).
+    PickDesktop,
+    /// Turn `follow_on_move` on or off (`desktop` is ignored).
+    ToggleFollow,
+    /// Minimize every window on the current desktop; again restores them
+    /// (`desktop` is ignored).
+    ShowDesktop,
+    /// Bring the window last focused on any other desktop over to this one
+    /// (`desktop` is ignored).
+    PullWindow,
+    /// Trade places with the next desktop and follow (`desktop` is ignored).
+    /// Windows can't reorder desktops through winvd, so their windows and
+    /// names are swapped instead; wallpapers stay where they are.
+    SwapNext,
+    /// Trade places with the previous desktop, like `swap_next`.
+    SwapPrevious,
+    /// Switch to the desktop, then start the binding's `command` there.
+    Launch,
+}
+
+impl Action {
+    /// Whether the action uses the binding's `desktop` at all.
+    pub fn targets_desktop(self) -> bool {
+        matches!(
+            self,
+            Action::Switch | Action::Move | Action::MoveFollow | Action::Launch
+        )
+    }
+}
+
+/// What middle-clicking the tray icon does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MiddleClick {
+    /// Nothing.
+    None,
+    /// Switch to the next desktop.
+    Next,
+    /// Switch to the previous desktop.
+    #[default]
+    Previous,
+    /// Switch back to the desktop active before the last switch.
+    ToggleLast,
+    /// Show the grid of desktops to pick from.
+    PickDesktop,
+}
+
+/// One hotkey binding: `key` plus `modifiers` runs `action` on `desktop` (0-based).
+///
+/// Keys are "0".."9", "a".."z", "f1".."f24", "numpad0".."numpad9", "left", "right",
+/// "home", "end", "`", "[", "]" and "pause". Modifiers are any of "ctrl", "alt", "shift", "win"
+/// (case-insensitive).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Binding {
+    pub key: String,
+    #[serde(default)]
+    pub modifiers: Vec<String>,
+    #[serde(default)]
+    pub action: Action,
+    #[serde(default)]
+    pub desktop: u32,
+    /// Keep firing while the keys are held, e.g. to sweep across desktops with
+    /// `next`. Repeats closer than `debounce_ms` are dropped, so they fire at
+    /// least that far apart; a large `debounce_ms` slows the sweep down.
+    #[serde(default)]
+    pub repeat: bool,
+    /// For `launch`: the program, document or URL to open, and its arguments,
+    /// e.g. `command = "wt.exe"`.
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub args: Option<String>,
+    /// For `launch`: pin the started program's first window to every desktop.
+    #[serde(default)]
+    pub pin: bool,
+}
+
+impl Binding {
+    fn new(key: &str, modifiers: &[&str], action: Action, desktop: u32) -> Self {
+        Self {
+            key: key.to_string(),
+            modifiers: modifiers.iter().map(|m| m.to_string()).collect(),
+            action,
+            desktop,
+            repeat: false,
+            command: None,
+            args: None,
+            pin: false,
+        }
+    }
+}
+
+/// Which windows a [`Rule`] applies to. Every field that is set must match;
+/// comparisons ignore case.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct RuleMatch {
+    /// Window class name, e.g. "Chrome_WidgetWin_1".
+    pub class: Option<String>,
+    /// Process image file name, e.g. "slack.exe".
+    pub exe: Option<String>,
+}
+
+impl RuleMatch {
+    /// `exe` is the full image path; only its file name is compared.
+    pub fn matches(&self, class: &str, exe: &str) -> bool {
+        if self.class.is_none() && self.exe.is_none() {
+            return false;
+        }
+        let exe_name = exe.rsplit(['\\', '/']).next().unwrap_or(exe);
+        let class_ok = self
+            .class
+            .as_ref()
+            .is_none_or(|c| c.eq_ignore_ascii_case(class));
+        let exe_ok = self
+            .exe
+            .as_ref()
+            .is_none_or(|e| e.eq_ignore_ascii_case(exe_name));
+        class_ok && exe_ok
+    }
+}
+
+/// New top-level windows matching `matcher` are moved to `desktop` (0-based).
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Rule {
+    #[serde(rename = "match")]
+    pub matcher: RuleMatch,
+    pub desktop: u32,
+}
+
+/// App to focus after switching to `desktop` (0-based) when nothing there has
+/// been focused yet.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DesktopDefault {
+    pub desktop: u32,
+    /// Process image file name, e.g. "code.exe"; compared ignoring case.
+    pub exe: String,
+    /// Start `exe` when it has no window on the desktop.
+    #[serde(default)]
+    pub launch: bool,
+}
+
+impl DesktopDefault {
+    /// `exe` is the full image path; only its file name is compared.
+    pub fn matches(&self, exe: &str) -> bool {
+        let exe_name = exe.rsplit(['\\', '/']).next().unwrap_or(exe);
+        self.exe.eq_ignore_ascii_case(exe_name)
+    }
+}
+
+/// A daily stretch of local time, "HH:MM" to "HH:MM". An `end` before
+/// `start` runs past midnight, e.g. "22:00" to "08:00".
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct QuietHours {
+    pub start: String,
+    pub end: String,
+}
+
+impl QuietHours {
+    /// Whether `minutes` past midnight falls inside. Unreadable times never match.
+    pub fn contains(&self, minutes: u32) -> bool {
+        let (Some(start), Some(end)) = (parse_time(&self.start), parse_time(&self.end)) else {
+            return false;
+        };
+        if start <= end {
+            (start..end).contains(&minutes)
+        } else {
+            minutes >= start || minutes < end
+        }
+    }
+}
+
+/// "HH:MM" as minutes past midnight.
+fn parse_time(text: &str) -> Option<u32> {
+    let (hours, minutes) = text.trim().split_once(':')?;
+    let (hours, minutes) = (hours.parse::<u32>().ok()?, minutes.parse::<u32>().ok()?);
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    pub bindings: Vec<Binding>,
+    pub rules: Vec<Rule>,
+    pub desktop_defaults: Vec<DesktopDefault>,
+    /// Windows never remembered as the one to focus after switching back,
+    /// matched like `rules`, e.g. `{ exe = "keepassxc.exe" }`.
+    pub focus_exclude: Vec<RuleMatch>,
+    /// Next/previous wrap from the last desktop to the first and back.
+    pub wrap_around: bool,
+    /// Switch along with the window on move-to-next/previous.
+    pub follow_relative_move: bool,
+    /// `move` bindings switch along with the window, like `move_follow`.
+    /// Toggled from the tray menu and saved back here.
+    pub follow_on_move: bool,
+    /// Next/previous pass over desktops that have no windows on them.
+    pub skip_empty: bool,
+    /// Desktops (0-based) that next/previous step through, in this order; empty
+    /// means all of them. Takes the place of `skip_empty` when set.
+    pub cycle_set: Vec<u32>,
+    /// Left-clicking the tray icon opens the menu too, not only right-clicking.
+    pub left_click_menu: bool,
+    /// Desktop (0-based) that double-clicking the tray icon switches to.
+    pub double_click_desktop: u32,
+    /// Middle-clicking the tray icon: "next", "previous", "toggle_last",
+    /// "pick_desktop" or "none".
+    pub middle_click: MiddleClick,
+    /// Switch to a desktop right after creating it from the menu or hotkey.
+    pub switch_to_new_desktop: bool,
+    /// Switching to a desktop past the last one first adds desktops up to it,
+    /// instead of doing nothing. Only up to 20 desktops are ever made this way.
+    pub auto_create_desktops: bool,
+    /// Ask before removing a desktop or merging all windows to desktop 1.
+    /// Ticking "Don't ask again" in the question turns this off.
+    pub confirm_destructive: bool,
+    /// Desktop count that the tray menu's "Ensure N desktops" tops up to.
+    /// Desktops are only ever added, never removed.
+    pub ensure_desktop_count: u32,
+    /// Win+digit bindings only fire with Shift held too, leaving Win+1..9 to
+    /// Windows for launching taskbar apps. A binding that already used
+    /// Win+Shift+digit (Move, by default) then needs Ctrl as well.
+    pub win_number_needs_shift: bool,
+    /// Release the hotkeys while a fullscreen window (e.g. a game) is focused.
+    pub suspend_in_fullscreen: bool,
+    /// Repeats of the same hotkey action on the same desktop within this many
+    /// milliseconds are ignored.
+    pub debounce_ms: u64,
+    /// After a switch, how many times to check that it took effect before
+    /// restoring focus, and how long to wait between checks. When unset, they
+    /// are picked to suit the running Windows version.
+    pub switch_retries: Option<u32>,
+    pub switch_retry_delay_ms: Option<u64>,
+    /// Pause after each switch before a queued one starts, so fast hotkey
+    /// presses apply one after another instead of racing the animation.
+    pub switch_grace_ms: u64,
+    /// With keyboard or mouse input this recent, focus is restored one retry
+    /// delay later, so a switch made while typing doesn't take the keystrokes
+    /// mid-word. 0 turns this off.
+    pub typing_guard_ms: u64,
+    /// Play `switch_sound_file` after each switch; without a file, a stock
+    /// Windows sound is used.
+    pub switch_sound: bool,
+    pub switch_sound_file: Option<PathBuf>,
+    /// Serve desktop changes and accept `goto` commands as JSON lines on
+    /// `127.0.0.1:<event_port>`. Off when unset.
+    pub event_port: Option<u16>,
+    /// Detail written to `d_switch.log`: "error", "info" or "debug".
+    /// `--verbose` on the command line means "debug".
+    pub log_level: Level,
+    /// Language of the tray menu and notifications: "en" or "de".
+    pub language: String,
+    /// Tray icon tooltip. `{desktop}` becomes "Desktop 3: Work" and `{hotkeys}`
+    /// the live hotkeys, e.g. "Win+1, Win+2"; Windows shows at most 127 characters.
+    pub tray_tooltip: String,
+    /// Flash a "Desktop N" indicator in the middle of the screen after each switch.
+    pub show_overlay: bool,
+    /// While the local time is inside, the overlay, balloons and switch sound
+    /// stay quiet; switching works as usual.
+    pub quiet_hours: Option<QuietHours>,
+    /// How unnamed desktops are shown in the tray menu, tooltip and overlay,
+    /// e.g. "[{index}]" with `{index}` the 1-based number. Unset means
+    /// "Desktop N".
+    pub name_template: Option<String>,
+    /// Tray icon background per desktop, in order, as "#rrggbb"; desktops
+    /// past the end or with an empty or unreadable entry get the default grey.
+    pub desktop_colors: Vec<String>,
+}
+
+impl Default for Config {
+    // Win+1..9 -> desktops 1..9, Win+Shift+1..9 sends the focused window there,
+    // Ctrl+Alt+1..9 sends it and follows. Alt+number is left to the focused app;
+    // Alt+Left/Right step through desktops (with Shift, taking the focused window),
+    // Alt+Home/End jump to the first and last one (likewise),
+    // Ctrl+Alt+Left/Right swap the current desktop with its neighbour,
+    // Alt+` bounces to the previous one,
+    // Alt+[ / Alt+] walk the back/forward history, Alt+N adds a desktop,
+    // Alt+Shift+N adds one and takes the focused window along,
+    // Alt+P pins or unpins the focused window, Alt+Shift+P its whole app,
+    // Alt+Shift+F toggles whether Win+Shift+number follows the window,
+    // Alt+D minimizes (and then restores) the windows on this desktop,
+    // Alt+Shift+0 pulls over the window last used on another desktop.
+    // Alt+F1..F3 reach desktops 10..12 for setups with more than nine, and
+    // Alt+G followed by digits reaches any desktop, as does picking from the
+    // grid Alt+Shift+G shows.
+    // Win+Pause turns all the others off and on again.
+    fn default() -> Self {
+        let sets: [(&[&str], Action); 3] = [
+            (&["win"], Action::Switch),
+            (&["win", "shift"], Action::Move),
+            (&["ctrl", "alt"], Action::MoveFollow),
+        ];
+        let mut bindings = Vec::new();
+        for (mods, action) in sets {
+            for n in 1..=9 {
+                bindings.push(Binding::new(&n.to_string(), mods, action, n - 1));
+            }
+        }
+        for n in 1..=3 {
+            bindings.push(Binding::new(
+                &format!("f{}", n),
+                &["alt"],
+                Action::Switch,
+                n + 8,
+            ));
+        }
+        bindings.push(Binding::new("right", &["alt"], Action::Next, 0));
+        bindings.push(Binding::new("left", &["alt"], Action::Previous, 0));
+        bindings.push(Binding::new(
+            "right",
+            &["alt", "shift"],
+            Action::MoveNext,
+            0,
+        ));
+        bindings.push(Binding::new(
+            "left",
+            &["alt", "shift"],
+            Action::MovePrevious,
+            0,
+        ));
+        bindings.push(Binding::new("right", &["ctrl", "alt"], Action::SwapNext, 0));
+        bindings.push(Binding::new(
+            "left",
+            &["ctrl", "alt"],
+            Action::SwapPrevious,
+            0,
+        ));
+        bindings.push(Binding::new("home", &["alt"], Action::First, 0));
+        bindings.push(Binding::new("end", &["alt"], Action::Last, 0));
+        bindings.push(Binding::new(
+            "home",
+            &["alt", "shift"],
+            Action::MoveFirst,
+            0,
+        ));
+        bindings.push(Binding::new("end", &["alt", "shift"], Action::MoveLast, 0));
+        bindings.push(Binding::new("`", &["alt"], Action::ToggleLast, 0));
+        bindings.push(Binding::new("[", &["alt"], Action::HistoryBack, 0));
+        bindings.push(Binding::new("]", &["alt"], Action::HistoryForward, 0));
+        bindings.push(Binding::new("n", &["alt"], Action::NewDesktop, 0));
+        bindings.push(Binding::new(
+            "n",
+            &["alt", "shift"],
+            Action::MoveToNewDesktop,
+            0,
+        ));
+        bindings.push(Binding::new("p", &["alt"], Action::TogglePinWindow, 0));
+        bindings.push(Binding::new(
+            "p",
+            &["alt", "shift"],
+            Action::TogglePinApp,
+            0,
+        ));
+        bindings.push(Binding::new(
+            "f",
+            &["alt", "shift"],
+            Action::ToggleFollow,
+            0,
+        ));
+        bindings.push(Binding::new("d", &["alt"], Action::ShowDesktop, 0));
+        bindings.push(Binding::new("0", &["alt", "shift"], Action::PullWindow, 0));
+        bindings.push(Binding::new("g", &["alt"], Action::GotoNumber, 0));
+        bindings.push(Binding::new("g", &["alt", "shift"], Action::PickDesktop, 0));
+        bindings.push(Binding::new("pause", &["win"], Action::TogglePause, 0));
+        Self {
+            bindings,
+            rules: Vec::new(),
+            desktop_defaults: Vec::new(),
+            focus_exclude: Vec::new(),
+            wrap_around: false,
+            skip_empty: false,
+            cycle_set: Vec::new(),
+            follow_relative_move: false,
+            follow_on_move: false,
+            left_click_menu: true,
+            double_click_desktop: 0,
+            middle_click: MiddleClick::Previous,
+            switch_to_new_desktop: true,
+            auto_create_desktops: false,
+            confirm_destructive: true,
+            ensure_desktop_count: 6,
+            win_number_needs_shift: false,
+            suspend_in_fullscreen: false,
+            debounce_ms: 120,
+            switch_retries: None,
+            switch_retry_delay_ms: None,
+            switch_grace_ms: 0,
+            typing_guard_ms: 50,
+            switch_sound: false,
+            switch_sound_file: None,
+            event_port: None,
+            log_level: Level::Info,
+            language: "en".to_string(),
+            tray_tooltip: "{desktop}".to_string(),
+            show_overlay: false,
+            quiet_hours: None,
+            name_template: None,
+            desktop_colors: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// `%APPDATA%\d_switch\config.toml`
+    pub fn default_path() -> Option<PathBuf> {
+        let appdata = std::env::var_os("APPDATA")?;
+        Some(PathBuf::from(appdata).join("d_switch").join("config.toml"))
+    }
+
+    /// A missing file is not an error: it yields the default bindings.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        };
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Write every setting to `path`, creating its folder. Comments in an
+    /// existing file are lost.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = toml::to_string(self).map_err(|e| e.to_string())?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        std::fs::write(path, text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quiet(start: &str, end: &str) -> QuietHours {
+        QuietHours {
+            start: start.to_string(),
+            end: end.to_string(),
+        }
+    }
+
+    fn at(hours: u32, minutes: u32) -> u32 {
+        hours * 60 + minutes
+    }
+
+    #[test]
+    fn range_within_a_day() {
+        let hours = quiet("09:00", "17:30");
+        assert!(!hours.contains(at(8, 59)));
+        assert!(hours.contains(at(9, 0)));
+        assert!(hours.contains(at(17, 29)));
+        assert!(!hours.contains(at(17, 30)));
+    }
+
+    #[test]
+    fn range_across_midnight() {
+        let hours = quiet("22:00", "07:00");
+        assert!(hours.contains(at(22, 0)));
+        assert!(hours.contains(at(23, 59)));
+        assert!(hours.contains(at(0, 0)));
+        assert!(hours.contains(at(6, 59)));
+        assert!(!hours.contains(at(7, 0)));
+        assert!(!hours.contains(at(12, 0)));
+        assert!(!hours.contains(at(21, 59)));
+    }
+
+    #[test]
+    fn equal_start_and_end_is_empty() {
+        let hours = quiet("08:00", "08:00");
+        assert!(!hours.contains(at(8, 0)));
+        assert!(!hours.contains(at(20, 0)));
+    }
+
+    #[test]
+    fn unreadable_times_never_match() {
+        assert_eq!(parse_time("25:00"), None);
+        assert_eq!(parse_time("12:60"), None);
+        assert_eq!(parse_time("7h"), None);
+        assert_eq!(parse_time(""), None);
+        assert_eq!(parse_time(" 07:05 "), Some(at(7, 5)));
+        assert!(!quiet("25:00", "07:00").contains(at(3, 0)));
+        assert!(!quiet("22:00", "7h").contains(at(23, 0)));
+    }
+}
@@ -0,0 +1,73 @@
+//! Line-delimited JSON over `127.0.0.1:<event_port>`, for Stream Deck and the like.
+//!
+//! Every desktop change is sent to all clients as `{"desktop":3,"name":"Work"}`
+//! (1-based). Clients may send `{"goto":3}` lines to switch.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize)]
+struct DesktopMessage<'a> {
+    desktop: u32,
+    name: &'a str,
+}
+
+#[derive(Deserialize)]
+struct Command {
+    goto: u32,
+}
+
+// A client that stops reading must not stall everyone else.
+const WRITE_TIMEOUT: Duration = Duration::from_millis(200);
+
+static CLIENTS: Mutex<Vec<TcpStream>> = Mutex::new(Vec::new());
+
+/// Bind the port and serve clients on background threads. `on_goto` gets the
+/// 1-based desktop number from each valid command.
+pub fn start(port: u16, on_goto: impl Fn(u32) + Send + Sync + 'static) -> std::io::Result<()> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    // Shared by the thread of every client.
+    let on_goto = Arc::new(on_goto);
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let Ok(reader) = stream.try_clone() else {
+                continue;
+            };
+            let _ = stream.set_write_timeout(Some(WRITE_TIMEOUT));
+            if let Ok(mut clients) = CLIENTS.lock() {
+                clients.push(stream);
+            }
+            let on_goto = Arc::clone(&on_goto);
+            std::thread::spawn(move || serve_commands(reader, &*on_goto));
+        }
+    });
+    Ok(())
+}
+
+/// Read commands until the client disconnects; malformed lines are ignored.
+fn serve_commands(stream: TcpStream, on_goto: &dyn Fn(u32)) {
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        if let Ok(command) = serde_json::from_str::<Command>(&line) {
+            on_goto(command.goto);
+        }
+    }
+}
+
+/// Tell every client about a desktop change; `desktop` is 1-based.
+/// Clients whose write fails are dropped.
+pub fn broadcast(desktop: u32, name: &str) {
+    let Ok(mut line) = serde_json::to_string(&DesktopMessage { desktop, name }) else {
+        return;
+    };
+    line.push('\n');
+    if let Ok(mut clients) = CLIENTS.lock() {
+        clients.retain_mut(|client| client.write_all(line.as_bytes()).is_ok());
+    }
+}
@@ -0,0 +1,37 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// A remembered focus target. Raw HWNDs don't survive a restart, so the
+/// window is described well enough to find it again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedFocus {
+    /// Desktop GUID as 32 hex digits.
+    pub desktop: String,
+    /// Full image path of the owning process.
+    pub exe: String,
+    pub class: String,
+    pub title: String,
+}
+
+/// `%APPDATA%\d_switch\focus.json`
+pub fn default_path() -> Option<PathBuf> {
+    let appdata = std::env::var_os("APPDATA")?;
+    Some(PathBuf::from(appdata).join("d_switch").join("focus.json"))
+}
+
+/// Missing or unreadable files yield an empty list.
+pub fn load(path: &Path) -> Vec<SavedFocus> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(path: &Path, entries: &[SavedFocus]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let text = serde_json::to_string_pretty(entries).map_err(std::io::Error::other)?;
+    std::fs::write(path, text)
+}
@@ -0,0 +1,106 @@
+/// Browser-style back/forward list of visited desktop indices.
+///
+/// `cursor` points at the entry for the current desktop. Recording a new
+/// visit drops everything after the cursor, like following a link after
+/// going back.
+#[derive(Debug, Default)]
+pub struct History {
+    entries: Vec<u32>,
+    cursor: usize,
+}
+
+/// Oldest entries are dropped beyond this.
+const MAX_ENTRIES: usize = 32;
+
+impl History {
+    /// Record a switch from `from` to `to`. The starting desktop is recorded
+    /// too the first time, so there is something to go back to.
+    pub fn record(&mut self, from: u32, to: u32) {
+        if self.entries.is_empty() {
+            self.entries.push(from);
+        } else {
+            self.entries.truncate(self.cursor + 1);
+        }
+        if self.entries.last() != Some(&to) {
+            self.entries.push(to);
+        }
+        if self.entries.len() > MAX_ENTRIES {
+            let excess = self.entries.len() - MAX_ENTRIES;
+            self.entries.drain(..excess);
+        }
+        self.cursor = self.entries.len() - 1;
+    }
+
+    /// Step back and return the desktop to switch to.
+    pub fn back(&mut self) -> Option<u32> {
+        self.cursor = self.cursor.checked_sub(1)?;
+        self.entries.get(self.cursor).copied()
+    }
+
+    /// Step forward and return the desktop to switch to.
+    pub fn forward(&mut self) -> Option<u32> {
+        if self.cursor + 1 >= self.entries.len() {
+            return None;
+        }
+        self.cursor += 1;
+        self.entries.get(self.cursor).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_record_keeps_the_starting_desktop() {
+        let mut history = History::default();
+        history.record(0, 2);
+        assert_eq!(history.entries, [0, 2]);
+        assert_eq!(history.cursor, 1);
+    }
+
+    #[test]
+    fn back_then_forward_returns() {
+        let mut history = History::default();
+        history.record(0, 1);
+        history.record(1, 2);
+        assert_eq!(history.back(), Some(1));
+        assert_eq!(history.back(), Some(0));
+        assert_eq!(history.forward(), Some(1));
+        assert_eq!(history.forward(), Some(2));
+        assert_eq!(history.forward(), None);
+    }
+
+    #[test]
+    fn record_after_back_drops_the_forward_entries() {
+        let mut history = History::default();
+        history.record(0, 1);
+        history.record(1, 2);
+        history.back();
+        history.record(1, 3);
+        assert_eq!(history.entries, [0, 1, 3]);
+        assert_eq!(history.forward(), None);
+        assert_eq!(history.back(), Some(1));
+    }
+
+    #[test]
+    fn back_at_the_start_stays_put() {
+        let mut history = History::default();
+        assert_eq!(history.back(), None);
+        history.record(0, 1);
+        history.back();
+        assert_eq!(history.back(), None);
+        assert_eq!(history.cursor, 0);
+    }
+
+    #[test]
+    fn oldest_entries_are_dropped_beyond_the_cap() {
+        let mut history = History::default();
+        for to in 1..=MAX_ENTRIES as u32 + 5 {
+            history.record(to - 1, to);
+        }
+        assert_eq!(history.entries.len(), MAX_ENTRIES);
+        assert_eq!(history.entries.first(), Some(&6));
+        assert_eq!(history.cursor, MAX_ENTRIES - 1);
+    }
+}
@@ -0,0 +1,907 @@
+//! Virtual desktop switching that brings back the window last focused on each
+//! desktop. The `d_switch` tray app is a thin shell around [`DesktopSwitcher`];
+//! other apps can embed it the same way.
+#![cfg(windows)]
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use windows::core::GUID;
+use windows::Win32::Foundation::HWND;
+use windows::Win32::System::SystemInformation::GetTickCount;
+use windows::Win32::System::Threading::{AttachThreadInput, GetCurrentThreadId};
+use windows::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
+use windows::Win32::UI::WindowsAndMessaging::{
+    BringWindowToTop, GetForegroundWindow, GetWindow, GetWindowThreadProcessId, IsIconic, IsWindow,
+    SetForegroundWindow, ShowWindow, GW_OWNER, SW_RESTORE,
+};
+
+/// Windows remembered per desktop, most recent first.
+const FOCUS_HISTORY_LEN: usize = 5;
+
+/// Why a switch or move didn't happen.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SwitchError {
+    /// A virtual desktop API call failed, e.g. because Explorer isn't running
+    /// or a Windows update changed the interfaces.
+    VirtualDesktopUnavailable(winvd::Error),
+    /// There is no desktop `index`; there are `count`.
+    IndexOutOfRange { index: u32, count: u32 },
+    /// The window is pinned to every desktop, so it can't be moved to one.
+    WindowPinned,
+    /// The API is working but wouldn't move the window.
+    MoveFailed(winvd::Error),
+}
+
+impl fmt::Display for SwitchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::VirtualDesktopUnavailable(e) => {
+                write!(f, "virtual desktop API unavailable: {:?}", e)
+            }
+            Self::IndexOutOfRange { index, count } => {
+                write!(f, "no desktop {} (there are {})", index + 1, count)
+            }
+            Self::WindowPinned => write!(f, "window is pinned to all desktops"),
+            Self::MoveFailed(e) => write!(f, "window not moved: {:?}", e),
+        }
+    }
+}
+
+impl std::error::Error for SwitchError {}
+
+impl From<winvd::Error> for SwitchError {
+    fn from(e: winvd::Error) -> Self {
+        Self::VirtualDesktopUnavailable(e)
+    }
+}
+
+/// Fails with `IndexOutOfRange` unless there is a desktop `index`.
+fn check_index(index: u32, count: u32) -> Result<(), SwitchError> {
+    if index >= count {
+        return Err(SwitchError::IndexOutOfRange { index, count });
+    }
+    Ok(())
+}
+
+/// The desktop and window operations the switching logic needs, so that
+/// logic can run against something other than the real virtual desktop API.
+pub trait DesktopBackend {
+    fn current_index(&self) -> winvd::Result<u32>;
+    fn count(&self) -> winvd::Result<u32>;
+    fn switch(&self, index: u32) -> winvd::Result<()>;
+    /// The id of desktop `index`, which unlike the index survives desktops
+    /// being removed before it.
+    fn desktop_id(&self, index: u32) -> winvd::Result<GUID>;
+    fn current_id(&self) -> winvd::Result<GUID>;
+    /// The id of the desktop `hwnd` is on.
+    fn window_desktop(&self, hwnd: HWND) -> winvd::Result<GUID>;
+    fn is_pinned(&self, hwnd: HWND) -> bool;
+    fn move_window(&self, hwnd: HWND, index: u32) -> winvd::Result<()>;
+    /// Whether `hwnd` is still an open window.
+    fn is_window(&self, hwnd: HWND) -> bool;
+    /// The foreground window; null when there is none.
+    fn foreground(&self) -> HWND;
+    /// The top of `hwnd`'s owner chain, or `hwnd` itself if it has no owner.
+    fn root_owner(&self, hwnd: HWND) -> HWND;
+    /// Time since the last keyboard or mouse input in this session.
+    fn idle_time(&self) -> Option<Duration>;
+    /// Restore `hwnd` if it is minimized and bring it to the foreground.
+    fn focus(&self, hwnd: HWND);
+}
+
+/// The real thing, through winvd and Win32.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Winvd;
+
+impl DesktopBackend for Winvd {
+    fn current_index(&self) -> winvd::Result<u32> {
+        winvd::get_current_desktop().and_then(|d| d.get_index())
+    }
+
+    fn count(&self) -> winvd::Result<u32> {
+        winvd::get_desktop_count()
+    }
+
+    fn switch(&self, index: u32) -> winvd::Result<()> {
+        winvd::switch_desktop(index)
+    }
+
+    fn desktop_id(&self, index: u32) -> winvd::Result<GUID> {
+        winvd::get_desktop(index).get_id()
+    }
+
+    fn current_id(&self) -> winvd::Result<GUID> {
+        winvd::get_current_desktop().and_then(|d| d.get_id())
+    }
+
+    fn window_desktop(&self, hwnd: HWND) -> winvd::Result<GUID> {
+        winvd::get_desktop_by_window(hwnd).and_then(|d| d.get_id())
+    }
+
+    fn is_pinned(&self, hwnd: HWND) -> bool {
+        winvd::is_pinned_window(hwnd).unwrap_or(false)
+    }
+
+    fn move_window(&self, hwnd: HWND, index: u32) -> winvd::Result<()> {
+        winvd::move_window_to_desktop(index, &hwnd)
+    }
+
+    fn is_window(&self, hwnd: HWND) -> bool {
+        unsafe { IsWindow(hwnd) }.as_bool()
+    }
+
+    fn foreground(&self) -> HWND {
+        unsafe { GetForegroundWindow() }
+    }
+
+    fn root_owner(&self, hwnd: HWND) -> HWND {
+        let mut owner = hwnd;
+        while let Ok(next) = unsafe { GetWindow(owner, GW_OWNER) } {
+            if next.0.is_null() {
+                break;
+            }
+            owner = next;
+        }
+        owner
+    }
+
+    fn idle_time(&self) -> Option<Duration> {
+        let mut info = LASTINPUTINFO {
+            cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+            dwTime: 0,
+        };
+        if !unsafe { GetLastInputInfo(&mut info) }.as_bool() {
+            return None;
+        }
+        // Both are tick counts that wrap after 49 days.
+        let idle = unsafe { GetTickCount() }.wrapping_sub(info.dwTime);
+        Some(Duration::from_millis(idle as u64))
+    }
+
+    fn focus(&self, hwnd: HWND) {
+        unsafe {
+            if IsIconic(hwnd).as_bool() {
+                let _ = ShowWindow(hwnd, SW_RESTORE);
+            }
+        }
+        set_foreground(hwnd);
+    }
+}
+
+/// Index one step forward or back from `current` within `0..count`, either
+/// wrapping around or clamped. `None` when there is nowhere to go.
+pub fn step_index(current: u32, count: u32, forward: bool, wrap: bool) -> Option<u32> {
+    if count == 0 {
+        return None;
+    }
+    let target = match (forward, wrap) {
+        (true, false) => (current + 1 < count).then_some(current + 1)?,
+        (false, false) => current.checked_sub(1)?,
+        (true, true) => (current + 1) % count,
+        (false, true) => current.checked_sub(1).unwrap_or(count - 1),
+    };
+    (target != current).then_some(target)
+}
+
+/// Like [`step_index`], but moving through `set` (desktop indices in cycling
+/// order) instead of every desktop. Entries at or past `count` are skipped; from
+/// a desktop outside the set, the first member is the target.
+pub fn step_in_set(
+    current: u32,
+    set: &[u32],
+    count: u32,
+    forward: bool,
+    wrap: bool,
+) -> Option<u32> {
+    let set: Vec<u32> = set.iter().copied().filter(|&i| i < count).collect();
+    let Some(pos) = set.iter().position(|&i| i == current) else {
+        return set.first().copied();
+    };
+    let next = step_index(pos as u32, set.len() as u32, forward, wrap)?;
+    Some(set[next as usize]).filter(|&target| target != current)
+}
+
+/// Switch `backend` to `index` and wait, checking up to `retries` times
+/// until it reports that desktop as current. The first check comes right
+/// after the switch; only later ones wait `delay`. Returns the desktop
+/// switched away from, if it was a different one, and how many waits it took
+/// (`None` if the desktop never showed as current).
+pub fn switch_with_retry<B: DesktopBackend>(
+    backend: &B,
+    index: u32,
+    retries: u32,
+    delay: Duration,
+) -> Result<(Option<u32>, Option<u32>), SwitchError> {
+    check_index(index, backend.count()?)?;
+    switch_checked(backend, index, retries, delay)
+}
+
+/// [`switch_with_retry`] for an index already checked against the count.
+fn switch_checked<B: DesktopBackend>(
+    backend: &B,
+    index: u32,
+    retries: u32,
+    delay: Duration,
+) -> Result<(Option<u32>, Option<u32>), SwitchError> {
+    let current = backend.current_index();
+    backend.switch(index)?;
+
+    // Switching can be async; retry briefly until the desktop becomes current.
+    let mut waits = None;
+    for attempt in 0..retries {
+        if attempt > 0 {
+            std::thread::sleep(delay);
+        }
+        let on_target = backend.current_index().map(|i| i == index).unwrap_or(true);
+        if on_target {
+            waits = Some(attempt);
+            break;
+        }
+    }
+    Ok((current.ok().filter(|&c| c != index), waits))
+}
+
+/// A remembered focus target, plus the top of its owner chain to fall back to
+/// once it is gone (e.g. a dialog that has since closed). Raw handle values.
+#[derive(Debug, Clone, Copy)]
+struct FocusEntry {
+    hwnd: usize,
+    owner: usize,
+    // When it was remembered, to compare entries across desktops.
+    at: Instant,
+}
+
+impl FocusEntry {
+    fn new(backend: &impl DesktopBackend, hwnd: HWND) -> Self {
+        Self {
+            hwnd: hwnd.0 as usize,
+            owner: backend.root_owner(hwnd).0 as usize,
+            at: Instant::now(),
+        }
+    }
+}
+
+fn raw_hwnd(raw: usize) -> HWND {
+    HWND(raw as *mut core::ffi::c_void)
+}
+
+/// Bring `hwnd` to the foreground. When Windows' foreground lock refuses a
+/// plain `SetForegroundWindow` (typical for Store/UWP apps), borrow the
+/// foreground thread's input state for a moment and try again.
+pub fn set_foreground(hwnd: HWND) {
+    unsafe {
+        if SetForegroundWindow(hwnd).as_bool() {
+            return;
+        }
+        let ours = GetCurrentThreadId();
+        let theirs = GetWindowThreadProcessId(GetForegroundWindow(), None);
+        let attached =
+            theirs != 0 && theirs != ours && AttachThreadInput(ours, theirs, true).as_bool();
+        let _ = BringWindowToTop(hwnd);
+        let _ = SetForegroundWindow(hwnd);
+        if attached {
+            let _ = AttachThreadInput(ours, theirs, false);
+        }
+    }
+}
+
+/// What a [`DesktopSwitcher`] keeps behind its lock.
+#[derive(Debug)]
+struct Memory {
+    // Keyed by desktop GUID: indices shift when an earlier desktop is removed.
+    // Store raw handle values to keep the switcher Send.
+    focus: HashMap<GUID, Vec<FocusEntry>>,
+    // Desktop we were on before the last switch.
+    previous: Option<u32>,
+    // Never recorded as a focus target (the host's own window).
+    ignored: usize,
+    retries: u32,
+    retry_delay: Duration,
+    // Input more recent than this delays focus restoring by one retry delay.
+    typing_guard: Duration,
+    // Waits the last switch needed before its desktop showed as current.
+    last_waits: Option<u32>,
+}
+
+/// Switches desktops and keeps the per-desktop focus history used to restore
+/// focus afterwards.
+///
+/// winvd keeps its COM objects per thread, so call this from a thread that
+/// has run `CoInitializeEx`. [`switch`](Self::switch) blocks for the retry
+/// loop; keep it off a message-loop thread. The switcher locks its focus
+/// history only for as long as it reads or updates it, never across the
+/// retry loop or focusing a window, so a message-loop thread can share it
+/// with the thread that switches.
+pub struct DesktopSwitcher<B = Winvd> {
+    backend: B,
+    memory: Mutex<Memory>,
+    // Called with the desktop index when a switch finds nothing to focus.
+    focus_fallback: Option<Box<dyn Fn(u32) + Send + Sync>>,
+    // True for windows that are never remembered as focus targets.
+    focus_exclude: Option<Box<dyn Fn(HWND) -> bool + Send + Sync>>,
+}
+
+impl<B: fmt::Debug> fmt::Debug for DesktopSwitcher<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DesktopSwitcher")
+            .field("backend", &self.backend)
+            .field("memory", &self.memory)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B: DesktopBackend + Default> Default for DesktopSwitcher<B> {
+    fn default() -> Self {
+        Self::with_backend(B::default())
+    }
+}
+
+impl DesktopSwitcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<B: DesktopBackend> DesktopSwitcher<B> {
+    /// A switcher working through `backend` instead of winvd.
+    pub fn with_backend(backend: B) -> Self {
+        Self {
+            backend,
+            memory: Mutex::new(Memory {
+                focus: HashMap::new(),
+                previous: None,
+                ignored: 0,
+                retries: 12,
+                retry_delay: Duration::from_millis(15),
+                typing_guard: Duration::from_millis(50),
+                last_waits: None,
+            }),
+            focus_fallback: None,
+            focus_exclude: None,
+        }
+    }
+
+    // A panic elsewhere can't leave the history half-updated in a way that
+    // matters, so a poisoned lock is used as is.
+    fn memory(&self) -> MutexGuard<'_, Memory> {
+        self.memory.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Never remember `hwnd` as a focus target, e.g. a tray app's own window
+    /// that is briefly in the foreground while its menu is open.
+    pub fn ignore_window(&self, hwnd: HWND) {
+        self.memory().ignored = hwnd.0 as usize;
+    }
+
+    /// After a switch, how many times to check that it took effect before
+    /// restoring focus, and how long to wait between checks.
+    pub fn set_retries(&self, retries: u32, delay: Duration) {
+        let mut memory = self.memory();
+        memory.retries = retries;
+        memory.retry_delay = delay;
+    }
+
+    /// While input is more recent than `guard`, wait one retry delay before
+    /// restoring focus to a window other than the foreground one, so typing
+    /// that started just as the switch happened isn't split across windows.
+    /// `Duration::ZERO` turns this off.
+    pub fn set_typing_guard(&self, guard: Duration) {
+        self.memory().typing_guard = guard;
+    }
+
+    /// Run `fallback` with the desktop index after a switch to a desktop that
+    /// has no remembered window left to focus. It runs on the switching thread.
+    pub fn set_focus_fallback(&mut self, fallback: impl Fn(u32) + Send + Sync + 'static) {
+        self.focus_fallback = Some(Box::new(fallback));
+    }
+
+    /// Don't remember the foreground window for the desktop when `exclude`
+    /// returns true for it, e.g. for always-on-top widgets. Like the fallback,
+    /// it runs on the switching thread.
+    pub fn set_focus_exclude(&mut self, exclude: impl Fn(HWND) -> bool + Send + Sync + 'static) {
+        self.focus_exclude = Some(Box::new(exclude));
+    }
+
+    /// How many retry delays the last switch waited before its desktop showed
+    /// as current; `None` if it never did, within the retries.
+    pub fn last_switch_waits(&self) -> Option<u32> {
+        self.memory().last_waits
+    }
+
+    /// The desktop active before the last switch.
+    pub fn previous_desktop(&self) -> Option<u32> {
+        self.memory().previous
+    }
+
+    /// For switches made elsewhere, e.g. seen through winvd's event listener.
+    pub fn set_previous_desktop(&self, desktop: Option<u32>) {
+        self.memory().previous = desktop;
+    }
+
+    /// Move `hwnd` to the front of the desktop's focus stack.
+    pub fn remember_focus(&self, desktop: GUID, hwnd: HWND) {
+        let entry = FocusEntry::new(&self.backend, hwnd);
+        let mut memory = self.memory();
+        let stack = memory.focus.entry(desktop).or_default();
+        stack.retain(|e| e.hwnd != entry.hwnd);
+        stack.insert(0, entry);
+        stack.truncate(FOCUS_HISTORY_LEN);
+    }
+
+    /// Add `hwnd` behind the desktop's existing entries, as when loading a
+    /// saved stack in most-recent-first order.
+    pub fn append_focus(&self, desktop: GUID, hwnd: HWND) {
+        let entry = FocusEntry::new(&self.backend, hwnd);
+        let mut memory = self.memory();
+        let stack = memory.focus.entry(desktop).or_default();
+        if stack.len() < FOCUS_HISTORY_LEN && !stack.iter().any(|e| e.hwnd == entry.hwnd) {
+            stack.push(entry);
+        }
+    }
+
+    /// Drop everything remembered for a desktop, e.g. after removing it.
+    pub fn forget_desktop(&self, desktop: GUID) {
+        self.memory().focus.remove(&desktop);
+    }
+
+    /// Every remembered (desktop, window) pair, most recent first per desktop.
+    pub fn focus_entries(&self) -> Vec<(GUID, HWND)> {
+        self.memory()
+            .focus
+            .iter()
+            .flat_map(|(&desktop, stack)| stack.iter().map(move |e| (desktop, raw_hwnd(e.hwnd))))
+            .collect()
+    }
+
+    /// Forget windows that have closed along with their owner, so the map stays
+    /// bounded and a recycled HWND value is never mistaken for the window we saw.
+    pub fn prune(&self) {
+        let alive = |raw| self.backend.is_window(raw_hwnd(raw));
+        self.memory().focus.retain(|_, stack| {
+            stack.retain(|e| alive(e.hwnd) || alive(e.owner));
+            !stack.is_empty()
+        });
+    }
+
+    /// Remember the foreground window as the current desktop's focus target.
+    pub fn save_focus(&self) {
+        let fg = self.backend.foreground();
+        let ignored = self.memory().ignored;
+        if fg.0.is_null()
+            || fg.0 as usize == ignored
+            || self
+                .focus_exclude
+                .as_ref()
+                .is_some_and(|exclude| exclude(fg))
+        {
+            return;
+        }
+        if let Ok(id) = self.backend.current_id() {
+            self.remember_focus(id, fg);
+        }
+    }
+
+    /// Whether `hwnd` is still a usable focus target for the desktop.
+    fn is_focus_candidate(&self, hwnd: HWND, desktop_id: GUID) -> bool {
+        if !self.backend.is_window(hwnd) {
+            return false;
+        }
+
+        // Only focus if the window is on that desktop, or is pinned.
+        let on_desktop = self
+            .backend
+            .window_desktop(hwnd)
+            .is_ok_and(|id| id == desktop_id);
+        self.backend.is_pinned(hwnd) || on_desktop
+    }
+
+    /// Whether `hwnd` is alive but now sits on a desktop other than
+    /// `desktop_id`, e.g. after being dragged there in Task View.
+    fn moved_away(&self, hwnd: HWND, desktop_id: GUID) -> bool {
+        if !self.backend.is_window(hwnd) || self.backend.is_pinned(hwnd) {
+            return false;
+        }
+        self.backend
+            .window_desktop(hwnd)
+            .is_ok_and(|id| id != desktop_id)
+    }
+
+    /// The window [`restore_focus`](Self::restore_focus) would bring forward on
+    /// the desktop, if any.
+    pub fn focus_target(&self, desktop_id: GUID) -> Option<HWND> {
+        self.focus_target_in(&self.memory(), desktop_id)
+    }
+
+    fn focus_target_in(&self, memory: &Memory, desktop_id: GUID) -> Option<HWND> {
+        memory
+            .focus
+            .get(&desktop_id)
+            .into_iter()
+            .flatten()
+            .flat_map(|e| [e.hwnd, e.owner])
+            .map(raw_hwnd)
+            .find(|&hwnd| self.is_focus_candidate(hwnd, desktop_id))
+    }
+
+    /// The most recently focused window remembered on any other desktop that is
+    /// still open there and not pinned, e.g. to pull it over to `desktop_id`.
+    pub fn recent_window_elsewhere(&self, desktop_id: GUID) -> Option<HWND> {
+        self.memory()
+            .focus
+            .iter()
+            .filter(|&(&id, _)| id != desktop_id)
+            .flat_map(|(_, stack)| stack)
+            .filter(|e| self.moved_away(raw_hwnd(e.hwnd), desktop_id))
+            .max_by_key(|e| e.at)
+            .map(|e| raw_hwnd(e.hwnd))
+    }
+
+    /// Focus the most recent window in the desktop's stack that still qualifies,
+    /// or failing that its root owner. Returns whether there was one.
+    ///
+    /// Entries whose window has moved to another desktop are dropped first, so
+    /// they don't linger in the stack or in what gets saved.
+    pub fn restore_focus(&self, desktop_id: GUID) -> bool {
+        let (target, typing_guard, delay) = {
+            let mut memory = self.memory();
+            if let Some(stack) = memory.focus.get_mut(&desktop_id) {
+                stack.retain(|e| {
+                    let hwnd = raw_hwnd(e.hwnd);
+                    let target = if self.backend.is_window(hwnd) {
+                        hwnd
+                    } else {
+                        raw_hwnd(e.owner)
+                    };
+                    !self.moved_away(target, desktop_id)
+                });
+            }
+            (
+                self.focus_target_in(&memory, desktop_id),
+                memory.typing_guard,
+                memory.retry_delay,
+            )
+        };
+        let Some(hwnd) = target else {
+            return false;
+        };
+
+        if hwnd != self.backend.foreground()
+            && self
+                .backend
+                .idle_time()
+                .is_some_and(|idle| idle < typing_guard)
+        {
+            std::thread::sleep(delay);
+        }
+        self.backend.focus(hwnd);
+        true
+    }
+
+    /// Switch to `desktop_index`, then restore that desktop's focus once the
+    /// switch has taken effect. Returns the desktop switched away from, if it
+    /// was a different one.
+    pub fn switch(&self, desktop_index: u32) -> Result<Option<u32>, SwitchError> {
+        self.save_focus();
+
+        check_index(desktop_index, self.backend.count()?)?;
+        let target_id = self.backend.desktop_id(desktop_index)?;
+        let (retries, delay) = {
+            let memory = self.memory();
+            (memory.retries, memory.retry_delay)
+        };
+        let (from, waits) = switch_checked(&self.backend, desktop_index, retries, delay)?;
+        {
+            let mut memory = self.memory();
+            memory.last_waits = waits;
+            if from.is_some() {
+                memory.previous = from;
+            }
+        }
+
+        if !self.restore_focus(target_id)
+            && let Some(fallback) = &self.focus_fallback
+        {
+            fallback(desktop_index);
+        }
+        Ok(from)
+    }
+
+    /// Send `hwnd` to `desktop_index` without switching. Pinned windows are
+    /// already on every desktop, so they are left alone (`WindowPinned`).
+    pub fn move_window(&self, hwnd: HWND, desktop_index: u32) -> Result<(), SwitchError> {
+        if self.backend.is_pinned(hwnd) {
+            return Err(SwitchError::WindowPinned);
+        }
+        check_index(desktop_index, self.backend.count()?)?;
+        self.backend
+            .move_window(hwnd, desktop_index)
+            .map_err(SwitchError::MoveFailed)
+    }
+
+    /// Send `hwnd` to `desktop_index` and switch there, leaving it in the
+    /// foreground. Nothing switches if the window isn't moved.
+    pub fn move_window_and_follow(
+        &self,
+        hwnd: HWND,
+        desktop_index: u32,
+    ) -> Result<(), SwitchError> {
+        self.move_window(hwnd, desktop_index)?;
+        // Make the moved window the focus target on the destination desktop.
+        let id = self.backend.desktop_id(desktop_index)?;
+        self.remember_focus(id, hwnd);
+        self.switch(desktop_index).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// Desktops and windows in memory. A switch only shows as current after
+    /// `lag` more checks, as a slow Windows 10 switch does.
+    struct MockBackend {
+        count: u32,
+        current: Cell<u32>,
+        lag: u32,
+        // Target of the last switch and the checks left before it lands.
+        pending: Cell<Option<(u32, u32)>>,
+        checks: Cell<u32>,
+        // Window (raw handle) -> index of the desktop it is on.
+        windows: RefCell<HashMap<usize, u32>>,
+        foreground: Cell<usize>,
+        focused: RefCell<Vec<usize>>,
+    }
+
+    impl MockBackend {
+        fn new(count: u32, current: u32) -> Self {
+            Self {
+                count,
+                current: Cell::new(current),
+                lag: 0,
+                pending: Cell::new(None),
+                checks: Cell::new(0),
+                windows: RefCell::new(HashMap::new()),
+                foreground: Cell::new(0),
+                focused: RefCell::new(Vec::new()),
+            }
+        }
+
+        fn with_lag(mut self, lag: u32) -> Self {
+            self.lag = lag;
+            self
+        }
+    }
+
+    fn id(index: u32) -> GUID {
+        GUID::from_u128(index as u128 + 1)
+    }
+
+    fn window(raw: usize) -> HWND {
+        raw_hwnd(raw)
+    }
+
+    impl DesktopBackend for MockBackend {
+        fn current_index(&self) -> winvd::Result<u32> {
+            self.checks.set(self.checks.get() + 1);
+            match self.pending.get() {
+                Some((target, 0)) => {
+                    self.current.set(target);
+                    self.pending.set(None);
+                }
+                Some((target, left)) => self.pending.set(Some((target, left - 1))),
+                None => {}
+            }
+            Ok(self.current.get())
+        }
+
+        fn count(&self) -> winvd::Result<u32> {
+            Ok(self.count)
+        }
+
+        fn switch(&self, index: u32) -> winvd::Result<()> {
+            self.pending.set(Some((index, self.lag)));
+            Ok(())
+        }
+
+        fn desktop_id(&self, index: u32) -> winvd::Result<GUID> {
+            Ok(id(index))
+        }
+
+        fn current_id(&self) -> winvd::Result<GUID> {
+            Ok(id(self.current.get()))
+        }
+
+        fn window_desktop(&self, hwnd: HWND) -> winvd::Result<GUID> {
+            let on = self.windows.borrow().get(&(hwnd.0 as usize)).copied();
+            on.map(id).ok_or(winvd::Error::WindowNotFound)
+        }
+
+        fn is_pinned(&self, _hwnd: HWND) -> bool {
+            false
+        }
+
+        fn move_window(&self, hwnd: HWND, index: u32) -> winvd::Result<()> {
+            self.windows.borrow_mut().insert(hwnd.0 as usize, index);
+            Ok(())
+        }
+
+        fn is_window(&self, hwnd: HWND) -> bool {
+            self.windows.borrow().contains_key(&(hwnd.0 as usize))
+        }
+
+        fn foreground(&self) -> HWND {
+            window(self.foreground.get())
+        }
+
+        fn root_owner(&self, hwnd: HWND) -> HWND {
+            hwnd
+        }
+
+        fn idle_time(&self) -> Option<Duration> {
+            None
+        }
+
+        fn focus(&self, hwnd: HWND) {
+            self.focused.borrow_mut().push(hwnd.0 as usize);
+            self.foreground.set(hwnd.0 as usize);
+        }
+    }
+
+    #[test]
+    fn step_next_from_the_last_desktop() {
+        assert_eq!(step_index(3, 4, true, true), Some(0));
+        assert_eq!(step_index(3, 4, true, false), None);
+    }
+
+    #[test]
+    fn step_previous_from_the_first_desktop() {
+        assert_eq!(step_index(0, 4, false, true), Some(3));
+        assert_eq!(step_index(0, 4, false, false), None);
+    }
+
+    #[test]
+    fn step_between_desktops() {
+        assert_eq!(step_index(1, 4, true, false), Some(2));
+        assert_eq!(step_index(1, 4, false, false), Some(0));
+        assert_eq!(step_index(1, 4, true, true), Some(2));
+        assert_eq!(step_index(1, 4, false, true), Some(0));
+    }
+
+    #[test]
+    fn step_with_one_desktop_goes_nowhere() {
+        for (forward, wrap) in [(true, true), (true, false), (false, true), (false, false)] {
+            assert_eq!(step_index(0, 1, forward, wrap), None);
+        }
+    }
+
+    #[test]
+    fn step_with_no_desktops_goes_nowhere() {
+        assert_eq!(step_index(0, 0, true, true), None);
+    }
+
+    const SET: [u32; 3] = [0, 2, 5];
+
+    #[test]
+    fn step_in_set_moves_between_members() {
+        assert_eq!(step_in_set(0, &SET, 6, true, false), Some(2));
+        assert_eq!(step_in_set(2, &SET, 6, true, false), Some(5));
+        assert_eq!(step_in_set(5, &SET, 6, false, false), Some(2));
+        assert_eq!(step_in_set(2, &SET, 6, false, false), Some(0));
+    }
+
+    #[test]
+    fn step_in_set_wraps_only_when_asked() {
+        assert_eq!(step_in_set(5, &SET, 6, true, true), Some(0));
+        assert_eq!(step_in_set(0, &SET, 6, false, true), Some(5));
+        assert_eq!(step_in_set(5, &SET, 6, true, false), None);
+        assert_eq!(step_in_set(0, &SET, 6, false, false), None);
+    }
+
+    #[test]
+    fn step_in_set_from_outside_goes_to_the_first_member() {
+        assert_eq!(step_in_set(3, &SET, 6, true, false), Some(0));
+        assert_eq!(step_in_set(3, &SET, 6, false, true), Some(0));
+    }
+
+    #[test]
+    fn step_in_set_skips_missing_desktops() {
+        // Desktop 5 doesn't exist with four desktops.
+        assert_eq!(step_in_set(2, &SET, 4, true, true), Some(0));
+        assert_eq!(step_in_set(2, &SET, 4, true, false), None);
+        assert_eq!(step_in_set(0, &SET, 4, false, true), Some(2));
+        assert_eq!(step_in_set(3, &[5, 1], 4, true, false), Some(1));
+    }
+
+    #[test]
+    fn switch_out_of_range_is_refused() {
+        let backend = MockBackend::new(3, 0);
+        let result = switch_with_retry(&backend, 3, 5, Duration::ZERO);
+        assert_eq!(
+            result,
+            Err(SwitchError::IndexOutOfRange { index: 3, count: 3 })
+        );
+        assert_eq!(backend.pending.get(), None);
+    }
+
+    #[test]
+    fn switch_past_the_ends_wraps_only_when_asked() {
+        let backend = MockBackend::new(4, 3);
+        assert_eq!(step_index(3, 4, true, false), None);
+        let next = step_index(3, 4, true, true).unwrap();
+        assert_eq!(
+            switch_with_retry(&backend, next, 1, Duration::ZERO),
+            Ok((Some(3), Some(0)))
+        );
+        assert_eq!(backend.current.get(), 0);
+
+        assert_eq!(step_index(0, 4, false, false), None);
+        let previous = step_index(0, 4, false, true).unwrap();
+        assert_eq!(
+            switch_with_retry(&backend, previous, 1, Duration::ZERO),
+            Ok((Some(0), Some(0)))
+        );
+        assert_eq!(backend.current.get(), 3);
+    }
+
+    #[test]
+    fn switch_retries_until_current() {
+        let backend = MockBackend::new(3, 0).with_lag(3);
+        assert_eq!(
+            switch_with_retry(&backend, 2, 12, Duration::ZERO),
+            Ok((Some(0), Some(3)))
+        );
+        assert_eq!(backend.current.get(), 2);
+        // One read before the switch, then four checks.
+        assert_eq!(backend.checks.get(), 5);
+    }
+
+    #[test]
+    fn switch_gives_up_after_the_retries() {
+        let backend = MockBackend::new(3, 0).with_lag(20);
+        assert_eq!(
+            switch_with_retry(&backend, 1, 5, Duration::ZERO),
+            Ok((Some(0), None))
+        );
+        assert_eq!(backend.current.get(), 0);
+        assert_eq!(backend.checks.get(), 6);
+    }
+
+    #[test]
+    fn switch_to_the_current_desktop_reports_no_previous() {
+        let backend = MockBackend::new(3, 1);
+        assert_eq!(
+            switch_with_retry(&backend, 1, 3, Duration::ZERO),
+            Ok((None, Some(0)))
+        );
+    }
+
+    #[test]
+    fn restore_focus_drops_a_window_moved_away() {
+        let sw = DesktopSwitcher::with_backend(MockBackend::new(2, 0));
+        sw.backend.windows.borrow_mut().insert(7, 0);
+        sw.remember_focus(id(0), window(7));
+        // Dragged to desktop 2 in Task View.
+        sw.backend.windows.borrow_mut().insert(7, 1);
+
+        assert!(!sw.restore_focus(id(0)));
+        assert!(sw.focus_entries().is_empty());
+        assert!(sw.backend.focused.borrow().is_empty());
+    }
+
+    #[test]
+    fn restore_focus_keeps_and_focuses_a_window_still_there() {
+        let sw = DesktopSwitcher::with_backend(MockBackend::new(2, 0));
+        sw.backend.windows.borrow_mut().insert(7, 0);
+        sw.remember_focus(id(0), window(7));
+
+        assert!(sw.restore_focus(id(0)));
+        assert_eq!(sw.focus_entries(), vec![(id(0), window(7))]);
+        assert_eq!(*sw.backend.focused.borrow(), vec![7]);
+    }
+}
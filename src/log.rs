@@ -0,0 +1,123 @@
+//! Timestamped diagnostics in `%APPDATA%\d_switch\d_switch.log`; there is no console.
+//!
+//! Lines are mirrored to OutputDebugString for DebugView. Once the file passes
+//! [`MAX_SIZE`] it is renamed to `d_switch.log.1` (replacing the previous one)
+//! and a new file is started.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use windows::core::PCWSTR;
+use windows::Win32::System::Diagnostics::Debug::OutputDebugStringW;
+use windows::Win32::System::SystemInformation::GetLocalTime;
+
+use crate::app::wstr;
+
+const MAX_SIZE: u64 = 1024 * 1024;
+
+/// How much to log; each level includes the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Level {
+    Error,
+    #[default]
+    Info,
+    Debug,
+}
+
+impl Level {
+    /// Same names as in the config file, case-insensitive.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "error" => Some(Self::Error),
+            "info" => Some(Self::Info),
+            "debug" => Some(Self::Debug),
+            _ => None,
+        }
+    }
+}
+
+struct Logger {
+    path: PathBuf,
+    file: File,
+    level: Level,
+}
+
+static LOGGER: Mutex<Option<Logger>> = Mutex::new(None);
+
+/// `%APPDATA%\d_switch\d_switch.log`
+pub fn default_path() -> Option<PathBuf> {
+    let appdata = std::env::var_os("APPDATA")?;
+    Some(PathBuf::from(appdata).join("d_switch").join("d_switch.log"))
+}
+
+/// Start writing to `path`. Until this succeeds, lines only reach the debugger.
+pub fn init(path: PathBuf, level: Level) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let file = open(&path)?;
+    if let Ok(mut logger) = LOGGER.lock() {
+        *logger = Some(Logger { path, file, level });
+    }
+    Ok(())
+}
+
+fn open(path: &PathBuf) -> std::io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+pub fn error(msg: &str) {
+    write(Level::Error, msg);
+}
+
+pub fn info(msg: &str) {
+    write(Level::Info, msg);
+}
+
+pub fn debug(msg: &str) {
+    write(Level::Debug, msg);
+}
+
+fn write(level: Level, msg: &str) {
+    let Ok(mut guard) = LOGGER.lock() else {
+        return;
+    };
+    if let Some(logger) = guard.as_ref()
+        && level > logger.level
+    {
+        return;
+    }
+
+    let t = unsafe { GetLocalTime() };
+    let line = format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03} {:<5} {}\n",
+        t.wYear,
+        t.wMonth,
+        t.wDay,
+        t.wHour,
+        t.wMinute,
+        t.wSecond,
+        t.wMilliseconds,
+        format!("{:?}", level).to_uppercase(),
+        msg
+    );
+    let wide = wstr(&format!("d_switch: {}", line));
+    unsafe { OutputDebugStringW(PCWSTR(wide.as_ptr())) };
+
+    let Some(logger) = guard.as_mut() else {
+        return;
+    };
+    if logger.file.metadata().is_ok_and(|m| m.len() > MAX_SIZE) {
+        let mut rotated = logger.path.clone().into_os_string();
+        rotated.push(".1");
+        let _ = std::fs::rename(&logger.path, rotated);
+        if let Ok(file) = open(&logger.path) {
+            logger.file = file;
+        }
+    }
+    let _ = logger.file.write_all(line.as_bytes());
+}
@@ -5,184 +5,2629 @@ fn main() {
     eprintln!("This app is Windows-only.");
 }
 
+#[cfg(windows)]
+mod cli;
+#[cfg(windows)]
+mod config;
+#[cfg(windows)]
+mod event_socket;
+#[cfg(windows)]
+mod focus_store;
+#[cfg(windows)]
+mod history;
+#[cfg(windows)]
+mod log;
+#[cfg(windows)]
+mod overlay;
+#[cfg(windows)]
+mod picker;
+#[cfg(windows)]
+mod prompt;
+#[cfg(windows)]
+mod settings;
+#[cfg(windows)]
+mod strings;
+#[cfg(windows)]
+mod window_index;
+
 #[cfg(windows)]
 mod app {
-    use std::collections::HashMap;
+    use std::cell::{Cell, RefCell};
+    use std::collections::{HashMap, HashSet};
     use std::ffi::OsStr;
     use std::iter::once;
     use std::os::windows::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
     use std::ptr::{null, null_mut};
-    use std::sync::{Mutex, OnceLock};
-    use std::time::Duration;
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+    use std::sync::{Arc, Condvar, Mutex, Weak};
+    use std::time::{Duration, Instant};
 
-    use windows::core::PCWSTR;
-    use windows::Win32::Foundation::{BOOL, HWND, LPARAM, LRESULT, WPARAM};
+    use windows::core::{GUID, PCWSTR, PWSTR};
+    use windows::Wdk::System::SystemServices::RtlGetVersion;
+    use windows::Win32::Foundation::{
+        CloseHandle, GetLastError, BOOL, COLORREF, ERROR_ALREADY_EXISTS, ERROR_SUCCESS, HWND,
+        LPARAM, LRESULT, RECT, RPC_E_CHANGED_MODE, S_FALSE, S_OK, WAIT_OBJECT_0, WPARAM,
+    };
+    use windows::Win32::Graphics::Gdi::{
+        CreateBitmap, CreateCompatibleBitmap, CreateCompatibleDC, CreateFontW, CreateSolidBrush,
+        DeleteDC, DeleteObject, DrawTextW, FillRect, GetDC, GetMonitorInfoW, MonitorFromWindow,
+        PtInRect, ReleaseDC, SelectObject, SetBkMode, SetTextColor, CLIP_DEFAULT_PRECIS,
+        DEFAULT_CHARSET, DEFAULT_PITCH, DT_CENTER, DT_SINGLELINE, DT_VCENTER, FF_DONTCARE, FW_BOLD,
+        MONITORINFO, MONITOR_DEFAULTTONEAREST, NONANTIALIASED_QUALITY, OUT_DEFAULT_PRECIS,
+        TRANSPARENT,
+    };
+    use windows::Win32::Media::Audio::{PlaySoundW, SND_ASYNC, SND_FILENAME, SND_NODEFAULT};
+    use windows::Win32::Storage::FileSystem::{
+        FindCloseChangeNotification, FindFirstChangeNotificationW, FindNextChangeNotification,
+        FILE_NOTIFY_CHANGE_FILE_NAME, FILE_NOTIFY_CHANGE_LAST_WRITE,
+    };
+    use windows::Win32::System::Com::{CoInitializeEx, CoUninitialize, COINIT_APARTMENTTHREADED};
+    use windows::Win32::System::DataExchange::COPYDATASTRUCT;
     use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+    use windows::Win32::System::Registry::{
+        RegDeleteKeyValueW, RegGetValueW, RegSetKeyValueW, HKEY_CURRENT_USER, REG_SZ, RRF_RT_REG_SZ,
+    };
+    use windows::Win32::System::RemoteDesktop::{
+        WTSRegisterSessionNotification, WTSUnRegisterSessionNotification, NOTIFY_FOR_THIS_SESSION,
+    };
+    use windows::Win32::System::SystemInformation::{GetLocalTime, OSVERSIONINFOW};
+    use windows::Win32::System::Threading::{
+        CreateMutexW, GetProcessId, OpenProcess, QueryFullProcessImageNameW, WaitForSingleObject,
+        INFINITE, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+    use windows::Win32::UI::Accessibility::{SetWinEventHook, UnhookWinEvent, HWINEVENTHOOK};
+    use windows::Win32::UI::HiDpi::{
+        SetProcessDpiAwarenessContext, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
+    };
     use windows::Win32::UI::Input::KeyboardAndMouse::{
-        RegisterHotKey, UnregisterHotKey, HOT_KEY_MODIFIERS, MOD_ALT, MOD_NOREPEAT, VK_1,
+        GetDoubleClickTime, GetKeyState, RegisterHotKey, UnregisterHotKey, HOT_KEY_MODIFIERS,
+        MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT, MOD_WIN, VIRTUAL_KEY, VK_0, VK_A,
+        VK_CONTROL, VK_END, VK_ESCAPE, VK_F1, VK_HOME, VK_LCONTROL, VK_LEFT, VK_LMENU, VK_LSHIFT,
+        VK_LWIN, VK_MENU, VK_NUMPAD0, VK_OEM_3, VK_OEM_4, VK_OEM_6, VK_PAUSE, VK_RCONTROL,
+        VK_RETURN, VK_RIGHT, VK_RMENU, VK_RSHIFT, VK_RWIN, VK_SHIFT,
     };
     use windows::Win32::UI::Shell::{
-        Shell_NotifyIconW, NIF_ICON, NIF_MESSAGE, NIF_TIP, NIM_ADD, NIM_DELETE, NOTIFYICONDATAW,
+        ShellExecuteExW, ShellExecuteW, Shell_NotifyIconGetRect, Shell_NotifyIconW, NIF_ICON,
+        NIF_INFO, NIF_MESSAGE, NIF_SHOWTIP, NIF_TIP, NIIF_WARNING, NIM_ADD, NIM_DELETE, NIM_MODIFY,
+        NIM_SETVERSION, NIN_SELECT, NOTIFYICONDATAW, NOTIFYICONIDENTIFIER, NOTIFYICON_VERSION_4,
+        SEE_MASK_NOCLOSEPROCESS, SHELLEXECUTEINFOW,
     };
     use windows::Win32::UI::WindowsAndMessaging::{
-        AppendMenuW, CreatePopupMenu, CreateWindowExW, DefWindowProcW, DestroyWindow,
-        DispatchMessageW, GetCursorPos, GetForegroundWindow, GetMessageW, IsIconic, IsWindow,
-        LoadIconW, PostQuitMessage, RegisterClassW, SetForegroundWindow, ShowWindow,
-        TrackPopupMenu, TranslateMessage, CS_HREDRAW, CS_VREDRAW, CW_USEDEFAULT, HICON, HMENU,
-        IDI_APPLICATION, MF_SEPARATOR, MF_STRING, MSG, SW_RESTORE, TPM_BOTTOMALIGN, TPM_LEFTALIGN,
-        TPM_RIGHTBUTTON, WM_COMMAND, WM_CREATE, WM_DESTROY, WM_HOTKEY, WM_RBUTTONUP, WM_USER,
-        WNDCLASSW, WS_OVERLAPPEDWINDOW,
+        AppendMenuW, CallNextHookEx, CreateIconIndirect, CreatePopupMenu, CreateWindowExW,
+        DefWindowProcW, DestroyIcon, DestroyMenu, DestroyWindow, DispatchMessageW, EnumWindows,
+        FindWindowW, GetAncestor, GetClassNameW, GetCursorPos, GetForegroundWindow, GetMessageW,
+        GetShellWindow, GetSystemMetrics, GetWindowLongPtrW, GetWindowLongW, GetWindowRect,
+        GetWindowTextW, GetWindowThreadProcessId, IsIconic, IsWindow, IsWindowVisible, KillTimer,
+        MessageBoxW, PostMessageW, PostQuitMessage, RegisterClassW, RegisterWindowMessageW,
+        SetForegroundWindow, SetTimer, SetWindowLongPtrW, SetWindowsHookExW, ShowWindow,
+        TrackPopupMenu, TranslateMessage, UnhookWindowsHookEx, CREATESTRUCTW, CS_HREDRAW,
+        CS_VREDRAW, EVENT_OBJECT_DESTROY, EVENT_OBJECT_SHOW, GA_ROOT, GWLP_USERDATA, GWL_EXSTYLE,
+        HHOOK, HICON, HMENU, ICONINFO, KBDLLHOOKSTRUCT, MB_ICONERROR, MB_ICONINFORMATION, MB_OK,
+        MF_CHECKED, MF_GRAYED, MF_POPUP, MF_SEPARATOR, MF_STRING, MSG, MSLLHOOKSTRUCT,
+        OBJID_WINDOW, SM_CXSMICON, SW_MINIMIZE, SW_RESTORE, SW_SHOWNORMAL, TPM_BOTTOMALIGN,
+        TPM_LEFTALIGN, TPM_RIGHTBUTTON, WHEEL_DELTA, WH_KEYBOARD_LL, WH_MOUSE_LL,
+        WINEVENT_OUTOFCONTEXT, WM_COMMAND, WM_CONTEXTMENU, WM_COPYDATA, WM_CREATE, WM_DESTROY,
+        WM_ENDSESSION, WM_HOTKEY, WM_KEYDOWN, WM_LBUTTONDBLCLK, WM_LBUTTONUP, WM_MBUTTONUP,
+        WM_MOUSEMOVE, WM_MOUSEWHEEL, WM_NCCREATE, WM_NCDESTROY, WM_QUERYENDSESSION, WM_SYSKEYDOWN,
+        WM_TIMER, WM_USER, WM_WTSSESSION_CHANGE, WNDCLASSW, WS_EX_TOOLWINDOW, WS_POPUP,
+        WTS_CONSOLE_CONNECT, WTS_SESSION_LOGON, WTS_SESSION_UNLOCK,
     };
 
+    use crate::cli::{self, RunOptions};
+    use d_switch::{step_in_set, step_index, DesktopSwitcher, SwitchError};
+
+    use crate::config::{Action, Binding, Config, MiddleClick};
+    use crate::event_socket;
+    use crate::focus_store::{self, SavedFocus};
+    use crate::history::History;
+    use crate::log;
+    use crate::overlay;
+    use crate::picker;
+    use crate::prompt;
+    use crate::settings;
+    use crate::strings::{self, Label};
+    use crate::window_index::{self, window_index};
+
     const WM_TRAYICON: u32 = WM_USER + 1;
+    // NIN_SELECT | NINF_KEY: keyboard selection of the tray icon.
+    const NIN_KEYSELECT: u32 = NIN_SELECT + 1;
+    // Posted by the switch worker after a switch; wParam = desktop index.
+    const WM_SWITCH_DONE: u32 = WM_USER + 2;
+    // Posted by `d_switch.exe goto N` to the resident instance; wParam = desktop index.
+    const WM_REMOTE_GOTO: u32 = WM_USER + 3;
+    // Posted by the desktop event forwarder for any switch, ours or the OS's;
+    // wParam = new desktop index, lParam = old desktop index.
+    const WM_DESKTOP_CHANGED: u32 = WM_USER + 4;
+    // Posted by the config watcher when the config file has been written.
+    const WM_CONFIG_CHANGED: u32 = WM_USER + 5;
+    // Posted by `d_switch.exe reload` to the resident instance.
+    const WM_REMOTE_RELOAD: u32 = WM_USER + 6;
+
+    const WINDOW_CLASS: &str = "d_switch_hidden_window";
+
+    // WM_COPYDATA `dwData` values; the payload is a little-endian u32 desktop index.
+    const COPYDATA_SWITCH: usize = 0;
+    const COPYDATA_MOVE: usize = 1;
+    const COPYDATA_MOVE_FOLLOW: usize = 2;
 
-    const HOTKEY_FIRST: i32 = 1;
-    const HOTKEY_LAST: i32 = 9;
+    // Entries of the tray menu's "Desktops" submenu use these command IDs, one
+    // per desktop; the fixed entries start above them.
+    const MENU_DESKTOP_FIRST: usize = 1;
+    const MENU_DESKTOP_LAST: usize = 999;
 
     const MENU_EXIT_ID: usize = 1000;
+    const MENU_STARTUP_ID: usize = 1001;
+    const MENU_NEW_DESKTOP_ID: usize = 1002;
+    const MENU_REMOVE_DESKTOP_ID: usize = 1003;
+    const MENU_RENAME_DESKTOP_ID: usize = 1004;
+    const MENU_HOTKEYS_ID: usize = 1005;
+    const MENU_SETTINGS_ID: usize = 1006;
+    const MENU_ENSURE_DESKTOPS_ID: usize = 1007;
+    const MENU_ABOUT_ID: usize = 1008;
+    const MENU_MERGE_ID: usize = 1009;
+    const MENU_FOLLOW_ID: usize = 1010;
+    const MENU_RELOAD_ID: usize = 1011;
+
+    const RUN_KEY: &str = "Software\\Microsoft\\Windows\\CurrentVersion\\Run";
+    const RUN_VALUE: &str = "d_switch";
+
+    // Polls whether the cursor is still over the tray icon while the wheel hook is installed.
+    const TIMER_TRAY_HOVER: usize = 1;
+    // Drops closed windows from the focus map.
+    const TIMER_PRUNE_FOCUS: usize = 2;
+    const PRUNE_FOCUS_INTERVAL_MS: u32 = 3 * 60 * 1000;
+    // Checks whether the foreground window is fullscreen (`suspend_in_fullscreen`).
+    const TIMER_FULLSCREEN: usize = 3;
+    const FULLSCREEN_POLL_MS: u32 = 1000;
+    // Delays the left-click menu until it's clear no double click follows.
+    const TIMER_TRAY_CLICK: usize = 4;
+    // Commits goto mode when no key has been typed for a while.
+    const TIMER_GOTO: usize = 5;
+    const GOTO_TIMEOUT_MS: u32 = 1500;
+    // More digits than any realistic desktop count.
+    const GOTO_MAX_DIGITS: usize = 3;
+
+    // Wheel steps closer together than this are dropped, so one notch is one desktop.
+    const WHEEL_DEBOUNCE: Duration = Duration::from_millis(100);
+
+    // A `launch` binding waits this long for its switch before starting the
+    // program anyway, then this long for the program's window, checking this often.
+    const LAUNCH_SWITCH_WAIT: Duration = Duration::from_secs(1);
+    const LAUNCH_WINDOW_WAIT: Duration = Duration::from_secs(5);
+    const LAUNCH_POLL: Duration = Duration::from_millis(50);
+
+    // Broadcast by Explorer when the taskbar is (re)created; registered in WM_CREATE.
+    static TASKBAR_CREATED: AtomicU32 = AtomicU32::new(0);
+
+    // Set by `--dry-run`: switches, moves, pins and desktop changes are only
+    // logged. Checked from the UI thread and the workers alike.
+    static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+    /// Digits typed after the goto hotkey, collected by a keyboard hook.
+    struct GotoMode {
+        // Raw HHOOK, and the window owning the timeout timer (raw HWND).
+        hook: usize,
+        hwnd: usize,
+        digits: String,
+    }
 
     #[derive(Default)]
     struct State {
-        // Store raw handle value to keep State Send+Sync.
-        last_focus_by_desktop: HashMap<u32, usize>,
+        config: Config,
+        // Where `config` came from, and where the settings window saves it.
+        config_path: Option<PathBuf>,
+        // Registered hotkey id -> action and target desktop index.
+        hotkeys: HashMap<i32, (Action, u32)>,
+        // Latest switch target not yet picked up by the worker, and whether it
+        // goes into the back/forward history. Requests that arrive while a
+        // switch is running overwrite each other.
+        pending_switch: Option<(u32, bool)>,
+        // Target of the switch the worker is running, until it has taken effect
+        // (or the retries ran out) and the grace period has passed.
+        switch_in_flight: Option<u32>,
+        history: History,
+        // Raw HICON currently shown in the tray (we own it and destroy it on replace).
+        tray_icon: usize,
+        // Low-level mouse hook (raw HHOOK) installed while hovering the tray icon,
+        // and the icon's screen rect at that time.
+        wheel_hook: usize,
+        tray_hover_rect: Option<RECT>,
+        // Partial wheel delta and time of the last step taken.
+        wheel_accum: i32,
+        last_wheel_step: Option<Instant>,
+        // winvd's event listener; dropping it stops the listener thread.
+        desktop_events: Option<winvd::DesktopEventThread>,
+        // Consecutive failed winvd calls while switching, and whether the
+        // "API unavailable" balloon has been shown (it is shown only once).
+        winvd_failures: u32,
+        winvd_warned: bool,
+        // Hotkeys are unregistered while a fullscreen window has the foreground,
+        // and while paused from the menu or the toggle hotkey.
+        suspended_for_fullscreen: bool,
+        hotkeys_paused: bool,
+        // Set by a tray double click so its closing button-up doesn't open the menu.
+        swallow_tray_click: bool,
+        // Last hotkey action and target, and when it fired, for debouncing.
+        last_hotkey: Option<((Action, u32), Instant)>,
+        goto_mode: Option<GotoMode>,
+        // Windows the show-desktop hotkey minimized (raw HWNDs, front to back),
+        // and the desktop they are on; the next press there restores them.
+        peeked: Option<(GUID, Vec<usize>)>,
+        // Window a Shift+click in the open tray menu moves (raw HWND), taken
+        // before the menu itself took the foreground.
+        menu_window: usize,
+        // Chosen at startup from the Windows build; Windows 10's when unknown.
+        retry_profile: Option<&'static RetryProfile>,
     }
 
-    static STATE: OnceLock<Mutex<State>> = OnceLock::new();
+    const WINVD_FAILURE_LIMIT: u32 = 3;
 
-    fn state() -> &'static Mutex<State> {
-        STATE.get_or_init(|| Mutex::new(State::default()))
+    /// Switch retry settings for when the config leaves them out.
+    struct RetryProfile {
+        name: &'static str,
+        retries: u32,
+        delay_ms: u64,
     }
 
-    fn wstr(s: &str) -> Vec<u16> {
+    // Windows 10 can take well over 100 ms to make a switch current.
+    const RETRY_PROFILE_WIN10: RetryProfile = RetryProfile {
+        name: "Windows 10",
+        retries: 12,
+        delay_ms: 15,
+    };
+    // Windows 11 is usually done within a few ms, so check more often.
+    const RETRY_PROFILE_WIN11: RetryProfile = RetryProfile {
+        name: "Windows 11",
+        retries: 20,
+        delay_ms: 5,
+    };
+    const WIN11_FIRST_BUILD: u32 = 22000;
+
+    // `auto_create_desktops` never goes past this many, whatever the target.
+    const AUTO_CREATE_MAX_DESKTOPS: u32 = 20;
+
+    /// What the window, its hooks and the worker threads share. `run` owns
+    /// the `Arc`; the window carries a pointer to it in GWLP_USERDATA, and
+    /// every thread started is handed a clone.
+    struct App {
+        state: Mutex<State>,
+        // Signalled (with the state lock) when `pending_switch` is set.
+        switch_ready: Condvar,
+        // Focus history and the previous desktop. It locks itself, and only
+        // briefly, so the UI thread can use it while the worker switches.
+        switcher: DesktopSwitcher,
+    }
+
+    thread_local! {
+        // The window whose App the UI thread's low-level hooks use; Windows
+        // calls them without any context. Set in WM_CREATE (raw HWND).
+        static HOOK_WINDOW: Cell<usize> = const { Cell::new(0) };
+        // The rules worker's App, for `rules_event_proc`, and the windows it
+        // has already seen shown (raw HWND values).
+        static RULES_APP: RefCell<Option<Arc<App>>> = const { RefCell::new(None) };
+        static RULED_WINDOWS: RefCell<HashSet<usize>> = RefCell::new(HashSet::new());
+    }
+
+    /// The App handed to the window in `CreateWindowExW`, or `None` for
+    /// messages before WM_NCCREATE (WM_GETMINMAXINFO) or after WM_NCDESTROY.
+    /// The window holds one reference of its own until WM_NCDESTROY.
+    unsafe fn window_app(hwnd: HWND) -> Option<Arc<App>> {
+        let ptr = unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *const App;
+        if ptr.is_null() {
+            return None;
+        }
+        unsafe {
+            Arc::increment_strong_count(ptr);
+            Some(Arc::from_raw(ptr))
+        }
+    }
+
+    /// The App for a low-level hook running on the UI thread.
+    unsafe fn hook_app() -> Option<Arc<App>> {
+        let hwnd = HWND(HOOK_WINDOW.get() as *mut core::ffi::c_void);
+        if hwnd.is_invalid() {
+            return None;
+        }
+        unsafe { window_app(hwnd) }
+    }
+
+    fn previous_desktop(app: &App) -> Option<u32> {
+        app.switcher.previous_desktop()
+    }
+
+    /// In a dry run, log `action` ("switch to desktop 2") and return true so the
+    /// caller skips it.
+    fn dry_run(action: impl FnOnce() -> String) -> bool {
+        if !DRY_RUN.load(Ordering::Relaxed) {
+            return false;
+        }
+        log::info(&format!("dry run: would {}", action()));
+        true
+    }
+
+    /// Write `config` to `path`; in a dry run, only log that `change` would
+    /// have been written.
+    fn save_config(config: &Config, path: &Path, change: &str) -> Result<(), String> {
+        if dry_run(|| format!("write {} to {}", change, path.display())) {
+            return Ok(());
+        }
+        config.save(path)
+    }
+
+    /// A window for the log, e.g. "0x1a2b (Notepad)".
+    unsafe fn describe_window(hwnd: HWND) -> String {
+        format!("{:#x} ({})", hwnd.0 as usize, unsafe { window_class(hwnd) })
+    }
+
+    pub(crate) fn wstr(s: &str) -> Vec<u16> {
         OsStr::new(s).encode_wide().chain(once(0)).collect()
     }
 
-    unsafe fn save_focus_for_current_desktop(app_hwnd: HWND) {
-        let fg = unsafe { GetForegroundWindow() };
-        if fg.0.is_null() || fg == app_hwnd {
-            return;
+    unsafe extern "system" fn collect_window(hwnd: HWND, lparam: LPARAM) -> BOOL {
+        let windows = unsafe { &mut *(lparam.0 as *mut Vec<HWND>) };
+        windows.push(hwnd);
+        BOOL(1)
+    }
+
+    /// All top-level windows, in z-order.
+    pub(crate) unsafe fn top_level_windows() -> Vec<HWND> {
+        let mut windows: Vec<HWND> = Vec::new();
+        let _ = unsafe {
+            EnumWindows(
+                Some(collect_window),
+                LPARAM(&mut windows as *mut Vec<HWND> as isize),
+            )
+        };
+        windows
+    }
+
+    /// Full image path of the process that owns `hwnd`.
+    unsafe fn window_process_path(hwnd: HWND) -> Option<String> {
+        let mut pid = 0u32;
+        unsafe { GetWindowThreadProcessId(hwnd, Some(&mut pid)) };
+        if pid == 0 {
+            return None;
         }
+        let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) }.ok()?;
+        let mut buf = [0u16; 1024];
+        let mut len = buf.len() as u32;
+        let res = unsafe {
+            QueryFullProcessImageNameW(
+                process,
+                PROCESS_NAME_WIN32,
+                PWSTR(buf.as_mut_ptr()),
+                &mut len,
+            )
+        };
+        let _ = unsafe { CloseHandle(process) };
+        res.ok()?;
+        Some(String::from_utf16_lossy(&buf[..len as usize]))
+    }
+
+    unsafe fn window_class(hwnd: HWND) -> String {
+        let mut buf = [0u16; 256];
+        let len = unsafe { GetClassNameW(hwnd, &mut buf) };
+        String::from_utf16_lossy(&buf[..len.max(0) as usize])
+    }
+
+    unsafe fn window_title(hwnd: HWND) -> String {
+        let mut buf = [0u16; 512];
+        let len = unsafe { GetWindowTextW(hwnd, &mut buf) };
+        String::from_utf16_lossy(&buf[..len.max(0) as usize])
+    }
 
-        let Ok(cur) = winvd::get_current_desktop() else {
+    /// Describe the focus map so it can be written out in WM_DESTROY.
+    unsafe fn save_focus_map(app: &App) {
+        let Some(path) = focus_store::default_path() else {
             return;
         };
-        let Ok(idx) = cur.get_index() else {
+        let focus = app.switcher.focus_entries();
+
+        let mut entries = Vec::new();
+        for (desktop, hwnd) in focus {
+            if !unsafe { IsWindow(hwnd) }.as_bool() {
+                continue;
+            }
+            let Some(exe) = (unsafe { window_process_path(hwnd) }) else {
+                continue;
+            };
+            entries.push(SavedFocus {
+                desktop: format!("{:032x}", desktop.to_u128()),
+                exe,
+                class: unsafe { window_class(hwnd) },
+                title: unsafe { window_title(hwnd) },
+            });
+        }
+        if let Err(e) = focus_store::save(&path, &entries) {
+            log::error(&format!("saving focus map failed: {}", e));
+        }
+    }
+
+    /// Re-resolve saved focus targets against the live windows: same process
+    /// image and class, preferring an identical title. Unmatched entries are dropped.
+    unsafe fn load_focus_map(app: &App) {
+        let Some(path) = focus_store::default_path() else {
             return;
         };
+        let saved = focus_store::load(&path);
+        if saved.is_empty() {
+            return;
+        }
+
+        let live: Vec<(HWND, String, String, String)> = unsafe { top_level_windows() }
+            .into_iter()
+            .filter_map(|hwnd| unsafe {
+                let exe = window_process_path(hwnd)?;
+                Some((hwnd, exe, window_class(hwnd), window_title(hwnd)))
+            })
+            .collect();
+
+        for entry in saved {
+            let Ok(desktop) = u128::from_str_radix(&entry.desktop, 16).map(GUID::from_u128) else {
+                continue;
+            };
+            let candidates = || {
+                live.iter().filter(|(_, exe, class, _)| {
+                    exe.eq_ignore_ascii_case(&entry.exe) && *class == entry.class
+                })
+            };
+            let found = candidates()
+                .find(|(_, _, _, title)| *title == entry.title)
+                .or_else(|| candidates().next());
+            // Entries were saved most recent first; keep that order.
+            if let Some(&(hwnd, ..)) = found {
+                app.switcher.append_focus(desktop, hwnd);
+            }
+        }
+    }
+
+    /// Blocks for the retry loop; call from the switch worker, not `wndproc`.
+    /// Returns true if the switch was issued.
+    unsafe fn try_switch_desktop(
+        app: &App,
+        app_hwnd: HWND,
+        desktop_index: u32,
+        record_history: bool,
+    ) -> bool {
+        let (retries, delay_ms, guard_ms, auto_create) = app
+            .state
+            .lock()
+            .map(|st| {
+                let profile = st.retry_profile.unwrap_or(&RETRY_PROFILE_WIN10);
+                (
+                    st.config.switch_retries.unwrap_or(profile.retries),
+                    st.config.switch_retry_delay_ms.unwrap_or(profile.delay_ms),
+                    st.config.typing_guard_ms,
+                    st.config.auto_create_desktops,
+                )
+            })
+            .unwrap_or((12, 15, 50, false));
+        if dry_run(|| {
+            let focus = winvd::get_desktop(desktop_index)
+                .get_id()
+                .ok()
+                .and_then(|id| app.switcher.focus_target(id));
+            match focus {
+                Some(hwnd) => format!(
+                    "switch to desktop {} and focus {}",
+                    desktop_index + 1,
+                    unsafe { describe_window(hwnd) }
+                ),
+                None => format!("switch to desktop {}", desktop_index + 1),
+            }
+        }) {
+            return false;
+        }
+        if auto_create {
+            create_missing_desktops(desktop_index);
+        }
+        log::debug(&format!("switching to {}", desktop_index));
+        let sw = &app.switcher;
+        sw.set_retries(retries, Duration::from_millis(delay_ms));
+        sw.set_typing_guard(Duration::from_millis(guard_ms));
+        let result = sw.switch(desktop_index);
+        // For tuning the retry profile from real switches.
+        if result.is_ok() {
+            match sw.last_switch_waits() {
+                Some(waits) => log::debug(&format!(
+                    "desktop {} current after {} of {} retries",
+                    desktop_index, waits, retries
+                )),
+                None if retries > 0 => log::info(&format!(
+                    "desktop {} not current after {} retries",
+                    desktop_index, retries
+                )),
+                None => {}
+            }
+        }
+
+        match result {
+            Ok(from) => {
+                if let Ok(mut st) = app.state.lock() {
+                    st.winvd_failures = 0;
+                    if let Some(from) = from
+                        && record_history
+                    {
+                        st.history.record(from, desktop_index);
+                    }
+                }
+                true
+            }
+            // The desktop doesn't exist, the API is fine.
+            Err(e @ SwitchError::IndexOutOfRange { .. }) => {
+                log::debug(&format!("switch skipped: {}", e));
+                false
+            }
+            Err(e) => {
+                log::error(&format!("switch to {} failed: {}", desktop_index, e));
+                unsafe { note_winvd_failure(app, app_hwnd) };
+                false
+            }
+        }
+    }
 
-        if let Ok(mut st) = state().lock() {
-            st.last_focus_by_desktop.insert(idx, fg.0 as usize);
+    /// Add desktops at the end until `desktop_index` exists. Targets beyond
+    /// AUTO_CREATE_MAX_DESKTOPS are left alone, and so fail as usual.
+    fn create_missing_desktops(desktop_index: u32) {
+        if desktop_index >= AUTO_CREATE_MAX_DESKTOPS {
+            return;
+        }
+        let Ok(count) = winvd::get_desktop_count() else {
+            return;
+        };
+        for _ in count..=desktop_index {
+            if let Err(e) = winvd::create_desktop() {
+                log::error(&format!("could not add a desktop: {:?}", e));
+                return;
+            }
+        }
+        if count <= desktop_index {
+            log::info(&format!(
+                "added desktops {} to {} for a switch",
+                count + 1,
+                desktop_index + 1
+            ));
         }
     }
 
-    unsafe fn restore_focus_for_desktop(desktop_index: u32) {
-        let hwnd = {
-            let Ok(st) = state().lock() else {
+    /// Count a failed winvd call. After WINVD_FAILURE_LIMIT in a row the API is
+    /// most likely unavailable on this Windows build; say so once.
+    unsafe fn note_winvd_failure(app: &App, app_hwnd: HWND) {
+        let warn = {
+            let Ok(mut st) = app.state.lock() else {
                 return;
             };
-            st.last_focus_by_desktop.get(&desktop_index).copied()
+            st.winvd_failures += 1;
+            let warn = st.winvd_failures >= WINVD_FAILURE_LIMIT && !st.winvd_warned;
+            st.winvd_warned |= warn;
+            warn
+        };
+        if warn {
+            log::error("virtual desktop API looks unavailable; warned the user");
+            unsafe {
+                show_balloon(
+                    app,
+                    app_hwnd,
+                    "d_switch",
+                    strings::text(Label::ApiUnavailable),
+                )
+            };
+        }
+    }
+
+    /// Queue a switch for the worker thread so `wndproc` returns immediately.
+    fn request_switch(app: &App, desktop_index: u32) {
+        queue_switch(app, desktop_index, true);
+    }
+
+    fn queue_switch(app: &App, desktop_index: u32, record_history: bool) {
+        if let Ok(mut st) = app.state.lock() {
+            st.pending_switch = Some((desktop_index, record_history));
+            app.switch_ready.notify_one();
+        }
+    }
+
+    /// The desktop next/previous step from: where queued or running switches
+    /// will land, so rapid presses count from there rather than from a
+    /// desktop that is about to be left.
+    fn navigation_base(app: &App) -> Option<u32> {
+        let queued = app.state.lock().ok().and_then(|st| {
+            st.pending_switch
+                .map(|(index, _)| index)
+                .or(st.switch_in_flight)
+        });
+        queued.or_else(|| {
+            winvd::get_current_desktop()
+                .and_then(|d| d.get_index())
+                .ok()
+        })
+    }
+
+    /// Back/forward through the history; these moves are not recorded again.
+    fn navigate_history(app: &App, forward: bool) {
+        let target = app.state.lock().ok().and_then(|mut st| {
+            if forward {
+                st.history.forward()
+            } else {
+                st.history.back()
+            }
+        });
+        if let Some(index) = target {
+            queue_switch(app, index, false);
+        }
+    }
+
+    /// COM for the calling thread, uninitialized on drop only if this call
+    /// initialized it. winvd keeps its COM objects per thread, so every thread
+    /// that calls it holds one of these for as long as it runs.
+    struct ComApartment {
+        initialized: bool,
+    }
+
+    impl ComApartment {
+        fn init() -> Self {
+            let hr = unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED) };
+            // S_FALSE means it already was, in our apartment: still ours to uninit.
+            let initialized = hr == S_OK || hr == S_FALSE;
+            if hr == RPC_E_CHANGED_MODE {
+                log::info("COM already initialized in another apartment; continuing without it");
+            } else if !initialized {
+                log::error(&format!("CoInitializeEx failed: {:?}", hr));
+            }
+            Self { initialized }
+        }
+    }
+
+    impl Drop for ComApartment {
+        fn drop(&mut self) {
+            if self.initialized {
+                unsafe { CoUninitialize() };
+            }
+        }
+    }
+
+    /// Runs queued switches (and the retry loop's sleeps) off the message-loop
+    /// thread. Only the latest queued target is acted on.
+    fn spawn_switch_worker(app: Arc<App>, app_hwnd: HWND) {
+        // HWND isn't Send; carry the raw value across.
+        let app_hwnd_raw = app_hwnd.0 as usize;
+        std::thread::spawn(move || {
+            let app_hwnd = HWND(app_hwnd_raw as *mut core::ffi::c_void);
+            let _com = ComApartment::init();
+            loop {
+                let target = {
+                    let Ok(st) = app.state.lock() else {
+                        return;
+                    };
+                    let Ok(mut st) = app
+                        .switch_ready
+                        .wait_while(st, |st| st.pending_switch.is_none())
+                    else {
+                        return;
+                    };
+                    let target = st.pending_switch.take();
+                    st.switch_in_flight = target.map(|(index, _)| index);
+                    target.map(|target| (target, st.config.switch_grace_ms))
+                };
+                let Some(((desktop_index, record_history), grace_ms)) = target else {
+                    continue;
+                };
+                if unsafe { try_switch_desktop(&app, app_hwnd, desktop_index, record_history) } {
+                    let _ = unsafe {
+                        PostMessageW(
+                            app_hwnd,
+                            WM_SWITCH_DONE,
+                            WPARAM(desktop_index as usize),
+                            LPARAM(0),
+                        )
+                    };
+                }
+                if grace_ms > 0 {
+                    std::thread::sleep(Duration::from_millis(grace_ms));
+                }
+                if let Ok(mut st) = app.state.lock() {
+                    st.switch_in_flight = None;
+                }
+            }
+        });
+    }
+
+    /// Called for every shown or destroyed object system-wide; only top-level
+    /// windows matter.
+    unsafe extern "system" fn rules_event_proc(
+        _hook: HWINEVENTHOOK,
+        event: u32,
+        hwnd: HWND,
+        id_object: i32,
+        id_child: i32,
+        _event_thread: u32,
+        _event_time: u32,
+    ) {
+        if hwnd.0.is_null() || id_object != OBJID_WINDOW.0 || id_child != 0 {
+            return;
+        }
+        if event == EVENT_OBJECT_DESTROY {
+            // The handle may be reused for a new window, which gets placed again.
+            RULED_WINDOWS.with(|seen| seen.borrow_mut().remove(&(hwnd.0 as usize)));
+            return;
+        }
+        if unsafe { GetAncestor(hwnd, GA_ROOT) } != hwnd {
+            return;
+        }
+        // A window is placed once, when first shown; later the user may move it
+        // freely, and showing it again (e.g. from the tray) leaves it be.
+        let first_show = RULED_WINDOWS.with(|seen| seen.borrow_mut().insert(hwnd.0 as usize));
+        if !first_show {
+            return;
+        }
+
+        let class = unsafe { window_class(hwnd) };
+        let exe = unsafe { window_process_path(hwnd) }.unwrap_or_default();
+        let target = RULES_APP.with(|app| {
+            let app = app.borrow();
+            let st = app.as_ref()?.state.lock().ok()?;
+            st.config
+                .rules
+                .iter()
+                .find(|rule| rule.matcher.matches(&class, &exe))
+                .map(|rule| rule.desktop)
+        });
+        let Some(desktop_index) = target else {
+            return;
+        };
+        if winvd::get_desktop_count().is_ok_and(|count| desktop_index < count)
+            && !dry_run(|| {
+                format!(
+                    "move new window {} to desktop {}",
+                    unsafe { describe_window(hwnd) },
+                    desktop_index + 1
+                )
+            })
+        {
+            let _ = winvd::move_window_to_desktop(desktop_index, &hwnd);
+        }
+    }
+
+    /// Whether `hwnd` matches a `focus_exclude` entry, so the switcher never
+    /// restores focus to it.
+    fn excluded_from_focus(app: &App, hwnd: HWND) -> bool {
+        let Some(exclude) = app
+            .state
+            .lock()
+            .ok()
+            .map(|st| st.config.focus_exclude.clone())
+            .filter(|e| !e.is_empty())
+        else {
+            return false;
         };
+        let class = unsafe { window_class(hwnd) };
+        let exe = unsafe { window_process_path(hwnd) }.unwrap_or_default();
+        exclude.iter().any(|m| m.matches(&class, &exe))
+    }
 
-        let Some(hwnd_raw) = hwnd else {
+    /// Switch fallback for a desktop with nothing remembered to focus: focus its
+    /// `desktop_defaults` app, or start it if it has no window there and
+    /// `launch` is set. Runs on the switch worker.
+    fn focus_desktop_default(app: &App, desktop_index: u32) {
+        let Some(default) = app.state.lock().ok().and_then(|st| {
+            st.config
+                .desktop_defaults
+                .iter()
+                .find(|d| d.desktop == desktop_index)
+                .cloned()
+        }) else {
             return;
         };
+        let on_desktop = window_index()
+            .lock()
+            .map(|mut index| index.windows_on(desktop_index))
+            .unwrap_or_default();
+        let window = on_desktop.into_iter().find(|&hwnd| {
+            unsafe { window_process_path(hwnd) }.is_some_and(|exe| default.matches(&exe))
+        });
+        if let Some(hwnd) = window {
+            if !dry_run(|| format!("focus {}", unsafe { describe_window(hwnd) })) {
+                d_switch::set_foreground(hwnd);
+            }
+        } else if default.launch && !dry_run(|| format!("start {}", default.exe)) {
+            log::info(&format!(
+                "starting {} on desktop {}",
+                default.exe,
+                desktop_index + 1
+            ));
+            let file = wstr(&default.exe);
+            let verb = wstr("open");
+            // App Paths are looked up too, so a bare "code.exe" is enough.
+            let result = unsafe {
+                ShellExecuteW(
+                    None,
+                    PCWSTR(verb.as_ptr()),
+                    PCWSTR(file.as_ptr()),
+                    PCWSTR(null()),
+                    PCWSTR(null()),
+                    SW_SHOWNORMAL,
+                )
+            };
+            if result.0 as usize <= 32 {
+                log::error(&format!("could not start {}", default.exe));
+            }
+        }
+    }
 
-        let hwnd = HWND(hwnd_raw as *mut core::ffi::c_void);
+    /// Open `file` with `args` through the shell. Returns the new process's id,
+    /// or 0 when the shell handed the file to a program already running.
+    unsafe fn shell_start(file: &str, args: Option<&str>) -> Option<u32> {
+        let verb = wstr("open");
+        let file = wstr(file);
+        let args = args.map(wstr);
+        let mut info = SHELLEXECUTEINFOW {
+            cbSize: std::mem::size_of::<SHELLEXECUTEINFOW>() as u32,
+            fMask: SEE_MASK_NOCLOSEPROCESS,
+            lpVerb: PCWSTR(verb.as_ptr()),
+            lpFile: PCWSTR(file.as_ptr()),
+            lpParameters: args.as_ref().map_or(PCWSTR(null()), |a| PCWSTR(a.as_ptr())),
+            nShow: SW_SHOWNORMAL.0,
+            ..Default::default()
+        };
+        unsafe { ShellExecuteExW(&mut info) }.ok()?;
+        if info.hProcess.is_invalid() {
+            return Some(0);
+        }
+        let pid = unsafe { GetProcessId(info.hProcess) };
+        let _ = unsafe { CloseHandle(info.hProcess) };
+        Some(pid)
+    }
 
-        if unsafe { IsWindow(hwnd) }.as_bool() == false {
+    /// Switch to the binding's desktop and start its `command` there, so the
+    /// new window opens on it. The rest runs on its own thread: once the
+    /// switch has landed, the program starts, and its first window is moved
+    /// to the desktop if it opened elsewhere, and pinned with `pin`. Programs
+    /// that hand off to another process (e.g. a running instance) are only
+    /// started; their window can't be told apart.
+    fn launch_on_desktop(app: &Arc<App>, binding: &Binding) {
+        let Some(command) = binding.command.clone().filter(|c| !c.trim().is_empty()) else {
+            log::error(&format!(
+                "launch binding {} has no command",
+                binding_label(binding)
+            ));
+            return;
+        };
+        let desktop_index = binding.desktop;
+        if dry_run(|| format!("start {} on desktop {}", command, desktop_index + 1)) {
             return;
         }
+        request_switch(app, desktop_index);
 
-        // Only focus if the window is on that desktop, or is pinned.
-        let pinned = winvd::is_pinned_window(hwnd).unwrap_or(false);
-        let on_desktop = winvd::get_desktop_by_window(hwnd)
-            .and_then(|d| d.get_index())
-            .map(|i| i == desktop_index)
+        let args = binding.args.clone();
+        let pin = binding.pin;
+        let app = Arc::clone(app);
+        std::thread::spawn(move || {
+            let _com = ComApartment::init();
+            let current = || {
+                winvd::get_current_desktop()
+                    .and_then(|d| d.get_index())
+                    .ok()
+            };
+            let deadline = Instant::now() + LAUNCH_SWITCH_WAIT;
+            while current() != Some(desktop_index) && Instant::now() < deadline {
+                std::thread::sleep(LAUNCH_POLL);
+            }
+
+            log::info(&format!(
+                "starting {} on desktop {}",
+                command,
+                desktop_index + 1
+            ));
+            let pid = match unsafe { shell_start(&command, args.as_deref()) } {
+                Some(0) => return,
+                Some(pid) => pid,
+                None => {
+                    log::error(&format!("could not start {}", command));
+                    return;
+                }
+            };
+
+            let deadline = Instant::now() + LAUNCH_WINDOW_WAIT;
+            while Instant::now() < deadline {
+                std::thread::sleep(LAUNCH_POLL);
+                let window = unsafe { top_level_windows() }.into_iter().find(|&w| {
+                    let mut owner = 0u32;
+                    unsafe { GetWindowThreadProcessId(w, Some(&mut owner)) };
+                    owner == pid && unsafe { IsWindowVisible(w) }.as_bool()
+                });
+                let Some(window) = window else {
+                    continue;
+                };
+                let on = winvd::get_desktop_by_window(window).and_then(|d| d.get_index());
+                if on.is_ok_and(|i| i != desktop_index) {
+                    move_window_to(&app, window, desktop_index);
+                }
+                if pin && winvd::pin_window(window).is_err() {
+                    log::error(&format!("could not pin {}", unsafe {
+                        describe_window(window)
+                    }));
+                }
+                return;
+            }
+            log::debug(&format!("no window from {} appeared", command));
+        });
+    }
+
+    /// Watches for new top-level windows and applies the config's `rules`.
+    /// Not started when there are none.
+    fn spawn_rules_worker(app: Arc<App>) {
+        let has_rules = app
+            .state
+            .lock()
+            .map(|st| !st.config.rules.is_empty())
             .unwrap_or(false);
-        if !pinned && !on_desktop {
+        if !has_rules {
+            return;
+        }
+        std::thread::spawn(move || unsafe {
+            let _com = ComApartment::init();
+            RULES_APP.with(|slot| *slot.borrow_mut() = Some(app));
+            // Out-of-context events are delivered through this thread's message loop.
+            // SHOW rather than CREATE: many windows are created hidden and only
+            // get their class and owner settled by the time they show. It fires
+            // on every re-show too, hence RULED_WINDOWS; DESTROY (just below SHOW)
+            // takes windows out of it again.
+            let hook = SetWinEventHook(
+                EVENT_OBJECT_DESTROY,
+                EVENT_OBJECT_SHOW,
+                None,
+                Some(rules_event_proc),
+                0,
+                0,
+                WINEVENT_OUTOFCONTEXT,
+            );
+            if hook.0.is_null() {
+                log::error("SetWinEventHook failed; window rules are disabled");
+                return;
+            }
+            let mut msg = MSG::default();
+            while GetMessageW(&mut msg, HWND(null_mut()), 0, 0).0 > 0 {
+                DispatchMessageW(&msg);
+            }
+            let _ = UnhookWinEvent(hook);
+        });
+    }
+
+    /// Watch the config file's folder and post `WM_CONFIG_CHANGED` after the file
+    /// is written. The log lives in the same folder, so a notification only
+    /// counts when the file's modification time has moved.
+    fn spawn_config_watcher(app: &App, app_hwnd: HWND) {
+        let Some(path) = app.state.lock().ok().and_then(|st| st.config_path.clone()) else {
+            return;
+        };
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        // HWND isn't Send; carry the raw value across.
+        let app_hwnd_raw = app_hwnd.0 as usize;
+        std::thread::spawn(move || unsafe {
+            let app_hwnd = HWND(app_hwnd_raw as *mut core::ffi::c_void);
+            let wide: Vec<u16> = dir.as_os_str().encode_wide().chain(once(0)).collect();
+            let Ok(handle) = FindFirstChangeNotificationW(
+                PCWSTR(wide.as_ptr()),
+                false,
+                FILE_NOTIFY_CHANGE_LAST_WRITE | FILE_NOTIFY_CHANGE_FILE_NAME,
+            ) else {
+                log::error(&format!(
+                    "cannot watch {}; config reload is off",
+                    dir.display()
+                ));
+                return;
+            };
+            let modified = || std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+            let mut last = modified();
+            while WaitForSingleObject(handle, INFINITE) == WAIT_OBJECT_0 {
+                let now = modified();
+                if now.is_some() && now != last {
+                    last = now;
+                    let _ = PostMessageW(app_hwnd, WM_CONFIG_CHANGED, WPARAM(0), LPARAM(0));
+                }
+                if FindNextChangeNotification(handle).is_err() {
+                    break;
+                }
+            }
+            let _ = FindCloseChangeNotification(handle);
+        });
+    }
+
+    /// Re-read the config file and re-register the hotkeys, after it changed on
+    /// disk or when asked to. A file that no longer parses is logged and the
+    /// running config kept; an `explicit` reload says so with a balloon too.
+    unsafe fn reload_config(app: &App, hwnd: HWND, explicit: bool) {
+        let Some(path) = app.state.lock().ok().and_then(|st| st.config_path.clone()) else {
+            return;
+        };
+        match Config::load(&path) {
+            Ok(config) => {
+                strings::set_language(&config.language);
+                if let Ok(mut st) = app.state.lock() {
+                    st.config = config;
+                }
+                log::info("config reloaded");
+                unsafe { update_fullscreen_timer(app, hwnd) };
+                unsafe { refresh_hotkeys(app, hwnd) };
+            }
+            Err(e) => {
+                log::error(&format!("config not reloaded, keeping the old one: {}", e));
+                if explicit {
+                    let text = strings::format(Label::ReloadFailed, e);
+                    unsafe { show_balloon(app, hwnd, "d_switch", &text) };
+                }
+            }
+        }
+    }
+
+    /// Forward winvd's desktop events to the window, so switches made elsewhere
+    /// (e.g. Win+Ctrl+Left/Right) keep the tray icon and toggle target current.
+    fn start_desktop_listener(app: &App, app_hwnd: HWND) {
+        let (sender, receiver) = std::sync::mpsc::channel::<winvd::DesktopEvent>();
+        let listener = match winvd::listen_desktop_events(sender) {
+            Ok(listener) => listener,
+            Err(e) => {
+                log::error(&format!("listen_desktop_events failed: {:?}", e));
+                return;
+            }
+        };
+        if let Ok(mut st) = app.state.lock() {
+            st.desktop_events = Some(listener);
+        }
+
+        let app_hwnd_raw = app_hwnd.0 as usize;
+        std::thread::spawn(move || {
+            let app_hwnd = HWND(app_hwnd_raw as *mut core::ffi::c_void);
+            // Resolving a Desktop to its index goes through winvd's per-thread COM objects.
+            let _com = ComApartment::init();
+            // Ends once the listener (and with it the sender) is dropped.
+            for event in receiver {
+                // Any of them may mean windows or desktop indices moved.
+                window_index::invalidate();
+                let winvd::DesktopEvent::DesktopChanged { new, old } = event else {
+                    continue;
+                };
+                let (Ok(new_index), Ok(old_index)) = (new.get_index(), old.get_index()) else {
+                    continue;
+                };
+                event_socket::broadcast(new_index + 1, &new.get_name().unwrap_or_default());
+                let _ = unsafe {
+                    PostMessageW(
+                        app_hwnd,
+                        WM_DESKTOP_CHANGED,
+                        WPARAM(new_index as usize),
+                        LPARAM(old_index as isize),
+                    )
+                };
+            }
+        });
+    }
+
+    fn switch_relative(app: &App, forward: bool) {
+        let Some(current) = navigation_base(app) else {
             return;
+        };
+        let Ok(count) = winvd::get_desktop_count() else {
+            return;
+        };
+        let (wrap, skip_empty, cycle_set) = app
+            .state
+            .lock()
+            .map(|st| {
+                (
+                    st.config.wrap_around,
+                    st.config.skip_empty,
+                    st.config.cycle_set.clone(),
+                )
+            })
+            .unwrap_or_default();
+        if !cycle_set.is_empty() {
+            if let Some(target) = step_in_set(current, &cycle_set, count, forward, wrap) {
+                request_switch(app, target);
+            }
+            return;
+        }
+        if !skip_empty {
+            if let Some(target) = step_index(current, count, forward, wrap) {
+                request_switch(app, target);
+            }
+            return;
+        }
+
+        let mut target = current;
+        // At most `count` steps visits every desktop once; landing back on
+        // `current` (or running off the end) means there is nowhere to go.
+        for _ in 0..count {
+            let Some(next) = step_index(target, count, forward, wrap) else {
+                return;
+            };
+            if next == current {
+                return;
+            }
+            target = next;
+            let has_windows = window_index()
+                .lock()
+                .is_ok_and(|mut index| index.is_occupied(target));
+            if has_windows {
+                request_switch(app, target);
+                return;
+            }
+        }
+    }
+
+    /// Send the foreground window one desktop forward or back, wrapping like
+    /// next/previous, and follow it if `follow_relative_move` is set.
+    unsafe fn move_window_relative(app: &App, app_hwnd: HWND, forward: bool) {
+        let Ok(current) = winvd::get_current_desktop().and_then(|d| d.get_index()) else {
+            return;
+        };
+        let Ok(count) = winvd::get_desktop_count() else {
+            return;
+        };
+        let (wrap, follow) = app
+            .state
+            .lock()
+            .map(|st| (st.config.wrap_around, st.config.follow_relative_move))
+            .unwrap_or((false, false));
+        let Some(target) = step_index(current, count, forward, wrap) else {
+            return;
+        };
+        if follow {
+            unsafe { move_window_and_follow(app, app_hwnd, target) };
+        } else {
+            let _ = unsafe { move_foreground_window(app, app_hwnd, target) };
+        }
+    }
+
+    /// Send the normal windows among `windows` (not tool windows, not our own)
+    /// to desktop `to`, e.g. a desktop's list from the window index. Returns
+    /// how many were moved.
+    unsafe fn move_windows(app_hwnd: HWND, windows: Vec<HWND>, to: u32) -> usize {
+        let mut moved = 0;
+        for window in windows {
+            let ex_style = unsafe { GetWindowLongW(window, GWL_EXSTYLE) } as u32;
+            if window == app_hwnd
+                || ex_style & WS_EX_TOOLWINDOW.0 != 0
+                || dry_run(|| {
+                    format!(
+                        "move {} to desktop {}",
+                        unsafe { describe_window(window) },
+                        to + 1
+                    )
+                })
+            {
+                continue;
+            }
+            if winvd::move_window_to_desktop(to, &window).is_ok() {
+                moved += 1;
+            }
+        }
+        window_index::invalidate();
+        moved
+    }
+
+    /// Stand-in for reordering, which winvd can't do: swap the windows and
+    /// names of the current desktop and the next or previous one, then switch
+    /// to where the current desktop's windows went. No wrapping at the ends.
+    unsafe fn swap_with_adjacent(app: &App, app_hwnd: HWND, forward: bool) {
+        let Ok(current) = winvd::get_current_desktop().and_then(|d| d.get_index()) else {
+            return;
+        };
+        let Ok(count) = winvd::get_desktop_count() else {
+            return;
+        };
+        let Some(other) = step_index(current, count, forward, false) else {
+            return;
+        };
+        if dry_run(|| format!("swap desktops {} and {}", current + 1, other + 1)) {
+            return;
+        }
+        // Both lists first, so the second pass doesn't move the first one back.
+        let (here, there) = window_index()
+            .lock()
+            .map(|mut index| (index.windows_on(current), index.windows_on(other)))
+            .unwrap_or_default();
+        unsafe {
+            move_windows(app_hwnd, here, other);
+            move_windows(app_hwnd, there, current);
+        }
+
+        let (a, b) = (winvd::get_desktop(current), winvd::get_desktop(other));
+        if let (Ok(name_a), Ok(name_b)) = (a.get_name(), b.get_name())
+            && (a.set_name(&name_b).is_err() || b.set_name(&name_a).is_err())
+        {
+            log::error("desktop names not swapped");
+        }
+        log::info(&format!(
+            "swapped desktops {} and {}",
+            current + 1,
+            other + 1
+        ));
+        request_switch(app, other);
+    }
+
+    /// Index of the first or last desktop.
+    fn end_desktop(last: bool) -> Option<u32> {
+        if !last {
+            return Some(0);
+        }
+        winvd::get_desktop_count().ok()?.checked_sub(1)
+    }
+
+    /// Send the foreground window to the first or last desktop, following it
+    /// if `follow_relative_move` is set. Pinned windows stay put.
+    unsafe fn move_window_to_end(app: &App, app_hwnd: HWND, last: bool) {
+        let Some(target) = end_desktop(last) else {
+            return;
+        };
+        let follow = app
+            .state
+            .lock()
+            .map(|st| st.config.follow_relative_move)
+            .unwrap_or(false);
+        if follow {
+            unsafe { move_window_and_follow(app, app_hwnd, target) };
+        } else {
+            let _ = unsafe { move_foreground_window(app, app_hwnd, target) };
+        }
+    }
+
+    /// Add a desktop at the end, switching to it if `switch_to_new_desktop` is set.
+    /// Failure (e.g. an unsupported Windows build) is reported with a balloon.
+    unsafe fn create_desktop(app: &App, hwnd: HWND) {
+        if dry_run(|| "create a desktop".to_string()) {
+            return;
+        }
+        let Ok(index) = winvd::create_desktop().and_then(|d| d.get_index()) else {
+            unsafe { show_balloon(app, hwnd, "d_switch", strings::text(Label::CreateFailed)) };
+            return;
+        };
+        let follow = app
+            .state
+            .lock()
+            .map(|st| st.config.switch_to_new_desktop)
+            .unwrap_or(false);
+        if follow {
+            request_switch(app, index);
+        }
+    }
+
+    /// Windows major, minor and build number.
+    fn windows_version() -> Option<(u32, u32, u32)> {
+        let mut version = OSVERSIONINFOW {
+            dwOSVersionInfoSize: std::mem::size_of::<OSVERSIONINFOW>() as u32,
+            ..Default::default()
+        };
+        // Unlike GetVersionExW, not capped at the manifested version.
+        unsafe { RtlGetVersion(&mut version) }.ok().ok()?;
+        Some((
+            version.dwMajorVersion,
+            version.dwMinorVersion,
+            version.dwBuildNumber,
+        ))
+    }
+
+    fn pick_retry_profile() -> &'static RetryProfile {
+        match windows_version() {
+            Some((_, _, build)) if build >= WIN11_FIRST_BUILD => &RETRY_PROFILE_WIN11,
+            _ => &RETRY_PROFILE_WIN10,
+        }
+    }
+
+    /// Version, Windows build and virtual desktop API status, for bug reports.
+    unsafe fn show_about(hwnd: HWND) {
+        let windows = match windows_version() {
+            Some((major, minor, build)) => format!("{}.{}.{}", major, minor, build),
+            None => strings::text(Label::AboutUnknown).to_string(),
+        };
+        let desktops = match winvd::get_desktop_count() {
+            Ok(count) => strings::format(Label::AboutApiWorking, count),
+            Err(e) => strings::format(Label::AboutApiFailed, format!("{:?}", e)),
+        };
+        let text = wstr(&strings::format_all(
+            Label::AboutText,
+            &[&env!("CARGO_PKG_VERSION"), &windows, &desktops],
+        ));
+        let title = wstr(strings::text(Label::About));
+        unsafe {
+            MessageBoxW(
+                hwnd,
+                PCWSTR(text.as_ptr()),
+                PCWSTR(title.as_ptr()),
+                MB_OK | MB_ICONINFORMATION,
+            )
+        };
+    }
+
+    /// Add desktops until there are `ensure_desktop_count`, staying on the
+    /// current one. Never removes any.
+    unsafe fn ensure_desktops(app: &App, hwnd: HWND) {
+        let target = app
+            .state
+            .lock()
+            .map(|st| st.config.ensure_desktop_count)
+            .unwrap_or(0);
+        let Ok(count) = winvd::get_desktop_count() else {
+            return;
+        };
+        if target > count && dry_run(|| format!("create {} desktops", target - count)) {
+            return;
+        }
+        for _ in count..target {
+            if winvd::create_desktop().is_err() {
+                unsafe { show_balloon(app, hwnd, "d_switch", strings::text(Label::CreateFailed)) };
+                return;
+            }
+        }
+        if target > count {
+            log::info(&format!("added {} desktops", target - count));
+        }
+    }
+
+    /// Ask `question` unless `confirm_destructive` is off. Ticking "Don't ask
+    /// again" turns it off and writes that to the config file.
+    unsafe fn confirm_destructive(app: &App, hwnd: HWND, question: &str) -> bool {
+        if !app
+            .state
+            .lock()
+            .map(|st| st.config.confirm_destructive)
+            .unwrap_or(true)
+        {
+            return true;
+        }
+        let Some(dont_ask) = (unsafe { prompt::confirm(question) }) else {
+            return false;
+        };
+        if dont_ask
+            && let Ok((config, path)) = app.state.lock().map(|mut st| {
+                st.config.confirm_destructive = false;
+                (st.config.clone(), st.config_path.clone())
+            })
+            && let Some(path) = path
+            && let Err(e) = save_config(&config, &path, "confirm_destructive = false")
+        {
+            log::error(&format!("confirmation setting not saved: {}", e));
+            unsafe { show_balloon(app, hwnd, "d_switch", strings::text(Label::SaveFailed)) };
+        }
+        true
+    }
+
+    /// Remove the current desktop after confirmation; its windows go to the
+    /// desktop before it (or after it, for the first one). The last desktop
+    /// is never removed.
+    unsafe fn remove_current_desktop(app: &App, hwnd: HWND) {
+        let (Ok(current), Ok(count)) = (winvd::get_current_desktop(), winvd::get_desktop_count())
+        else {
+            return;
+        };
+        let Ok(index) = current.get_index() else {
+            return;
+        };
+        if count <= 1 {
+            unsafe {
+                show_balloon(
+                    app,
+                    hwnd,
+                    "d_switch",
+                    strings::text(Label::RemoveOnlyDesktop),
+                )
+            };
+            return;
+        }
+
+        let question = strings::format(Label::RemoveDesktopQuestion, index + 1);
+        if !unsafe { confirm_destructive(app, hwnd, &question) } {
+            return;
+        }
+
+        let fallback = if index > 0 { index - 1 } else { 1 };
+        if dry_run(|| format!("remove desktop {}", index + 1)) {
+            return;
+        }
+        let removed_id = current.get_id().ok();
+        if winvd::remove_desktop(index, fallback).is_err() {
+            unsafe { show_balloon(app, hwnd, "d_switch", strings::text(Label::RemoveFailed)) };
+            return;
+        }
+        if let Some(id) = removed_id {
+            app.switcher.forget_desktop(id);
+        }
+
+        // Indices after the removed desktop shift down by one.
+        let expected = if fallback > index {
+            fallback - 1
+        } else {
+            fallback
+        };
+        let now = winvd::get_current_desktop()
+            .and_then(|d| d.get_index())
+            .unwrap_or(expected);
+        unsafe { update_tray_icon(app, hwnd, now) };
+    }
+
+    /// After confirmation, move every normal window (visible, not a tool window,
+    /// not pinned) to desktop 1 and switch there.
+    unsafe fn merge_all_to_first_desktop(app: &App, hwnd: HWND) {
+        if !unsafe { confirm_destructive(app, hwnd, strings::text(Label::MergeDesktopsQuestion)) } {
+            return;
+        }
+
+        // Windows winvd can't place (e.g. system surfaces) are in no desktop's
+        // list, so they are left alone.
+        let count = winvd::get_desktop_count().unwrap_or(0);
+        let others: Vec<HWND> = window_index()
+            .lock()
+            .map(|mut index| (1..count).flat_map(|i| index.windows_on(i)).collect())
+            .unwrap_or_default();
+        let moved = unsafe { move_windows(hwnd, others, 0) };
+        log::info(&format!("merged {} windows to desktop 1", moved));
+        request_switch(app, 0);
+    }
+
+    /// Ask for a new name for the current desktop. Empty input keeps the old one.
+    unsafe fn rename_current_desktop(app: &App, hwnd: HWND) {
+        let Ok(current) = winvd::get_current_desktop() else {
+            return;
+        };
+        let name = current.get_name().unwrap_or_default();
+        let Some(input) =
+            (unsafe { prompt::prompt_text(strings::text(Label::RenameDesktopTitle), &name) })
+        else {
+            return;
+        };
+        let input = input.trim();
+        if input.is_empty() || input == name {
+            return;
+        }
+        if dry_run(|| format!("rename the desktop to {:?}", input)) {
+            return;
+        }
+        if current.set_name(input).is_err() {
+            unsafe { show_balloon(app, hwnd, "d_switch", strings::text(Label::RenameFailed)) };
+            return;
+        }
+        unsafe { update_tray_tip(app, hwnd) };
+    }
+
+    /// Edit the bindings in the settings window; on Save, write the config file
+    /// and re-register the hotkeys so the changes apply right away.
+    unsafe fn open_settings(app: &App, hwnd: HWND) {
+        let Ok(config) = app.state.lock().map(|st| st.config.clone()) else {
+            return;
+        };
+        let Some(config) = (unsafe { settings::edit_settings(&config) }) else {
+            return;
+        };
+        let Ok(path) = app.state.lock().map(|mut st| {
+            st.config = config.clone();
+            st.config_path.clone()
+        }) else {
+            return;
+        };
+        if let Some(path) = path
+            && let Err(e) = save_config(&config, &path, "the edited settings")
+        {
+            log::error(&format!("settings not saved: {}", e));
+            unsafe { show_balloon(app, hwnd, "d_switch", strings::text(Label::SaveFailed)) };
+        }
+        unsafe { update_fullscreen_timer(app, hwnd) };
+        unsafe { refresh_hotkeys(app, hwnd) };
+    }
+
+    /// Turn `follow_on_move` on or off and write it to the config file.
+    unsafe fn set_follow_on_move(app: &App, hwnd: HWND, follow: bool) {
+        let Ok((config, path)) = app.state.lock().map(|mut st| {
+            st.config.follow_on_move = follow;
+            (st.config.clone(), st.config_path.clone())
+        }) else {
+            return;
+        };
+        log::info(if follow {
+            "following moved windows"
+        } else {
+            "not following moved windows"
+        });
+        let change = format!("follow_on_move = {}", follow);
+        if let Some(path) = path
+            && let Err(e) = save_config(&config, &path, &change)
+        {
+            log::error(&format!("follow setting not saved: {}", e));
+            unsafe { show_balloon(app, hwnd, "d_switch", strings::text(Label::SaveFailed)) };
+        }
+    }
+
+    fn follow_on_move(app: &App) -> bool {
+        app.state
+            .lock()
+            .map(|st| st.config.follow_on_move)
+            .unwrap_or(false)
+    }
+
+    /// Send the foreground window to `desktop_index` without switching.
+    /// Pinned windows are already on every desktop, so they are left alone.
+    /// Returns the moved window.
+    unsafe fn move_foreground_window(
+        app: &App,
+        app_hwnd: HWND,
+        desktop_index: u32,
+    ) -> Option<HWND> {
+        let fg = unsafe { GetForegroundWindow() };
+        if fg.0.is_null() || fg == app_hwnd {
+            return None;
+        }
+        move_window_to(app, fg, desktop_index)
+    }
+
+    /// Send `window` to `desktop_index` without switching. Returns it if moved.
+    fn move_window_to(app: &App, window: HWND, desktop_index: u32) -> Option<HWND> {
+        if dry_run(|| {
+            format!(
+                "move {} to desktop {}",
+                unsafe { describe_window(window) },
+                desktop_index + 1
+            )
+        }) {
+            return None;
+        }
+        match app.switcher.move_window(window, desktop_index) {
+            Ok(()) => {
+                window_index::invalidate();
+                Some(window)
+            }
+            // Already on every desktop; nothing to report.
+            Err(SwitchError::WindowPinned) => None,
+            Err(e) => {
+                log::error(&format!("move to {} failed: {}", desktop_index, e));
+                None
+            }
+        }
+    }
+
+    /// The window a Shift+pick in the tray menu or desktop grid sends away: the
+    /// foreground window, or if the taskbar took the foreground for a tray
+    /// click, the frontmost normal window on the current desktop.
+    unsafe fn window_to_move(app_hwnd: HWND) -> Option<HWND> {
+        let is_taskbar = |window| {
+            matches!(
+                unsafe { window_class(window) }.as_str(),
+                "Shell_TrayWnd" | "Shell_SecondaryTrayWnd"
+            )
+        };
+        let fg = unsafe { GetForegroundWindow() };
+        if !fg.0.is_null() && fg != app_hwnd && !is_taskbar(fg) {
+            return Some(fg);
+        }
+        // EnumWindows lists windows front to back.
+        unsafe { top_level_windows() }.into_iter().find(|&window| {
+            let ex_style = unsafe { GetWindowLongW(window, GWL_EXSTYLE) } as u32;
+            window != app_hwnd
+                && unsafe { IsWindowVisible(window) }.as_bool()
+                && ex_style & WS_EX_TOOLWINDOW.0 == 0
+                && !is_taskbar(window)
+                && winvd::is_window_on_current_desktop(window).unwrap_or(false)
+        })
+    }
+
+    fn shift_held() -> bool {
+        // The high bit of the state is set while the key is down.
+        (unsafe { GetKeyState(VK_SHIFT.0 as i32) }) < 0
+    }
+
+    /// Minimize the normal windows on the current desktop, leaving pinned ones
+    /// and other desktops alone. A second press on the same desktop restores
+    /// the ones minimized and still minimized.
+    unsafe fn toggle_show_desktop(app: &App, app_hwnd: HWND) {
+        let Ok(desktop) = winvd::get_current_desktop().and_then(|d| d.get_id()) else {
+            return;
+        };
+        let peeked = app.state.lock().ok().and_then(|mut st| st.peeked.take());
+        if let Some((id, windows)) = peeked
+            && id == desktop
+        {
+            if dry_run(|| format!("restore {} windows", windows.len())) {
+                return;
+            }
+            // Back to front, so the front window ends up on top again.
+            for &raw in windows.iter().rev() {
+                let hwnd = HWND(raw as *mut core::ffi::c_void);
+                if unsafe { IsWindow(hwnd).as_bool() && IsIconic(hwnd).as_bool() } {
+                    let _ = unsafe { ShowWindow(hwnd, SW_RESTORE) };
+                }
+            }
+            return;
+        }
+
+        let windows: Vec<HWND> = unsafe { top_level_windows() }
+            .into_iter()
+            .filter(|&window| {
+                let ex_style = unsafe { GetWindowLongW(window, GWL_EXSTYLE) } as u32;
+                window != app_hwnd
+                    && unsafe { IsWindowVisible(window) }.as_bool()
+                    && !unsafe { IsIconic(window) }.as_bool()
+                    && ex_style & WS_EX_TOOLWINDOW.0 == 0
+                    && !winvd::is_pinned_window(window).unwrap_or(false)
+                    && winvd::get_desktop_by_window(window)
+                        .and_then(|d| d.get_id())
+                        .is_ok_and(|id| id == desktop)
+            })
+            .collect();
+        if windows.is_empty() || dry_run(|| format!("minimize {} windows", windows.len())) {
+            return;
+        }
+        for &window in &windows {
+            let _ = unsafe { ShowWindow(window, SW_MINIMIZE) };
+        }
+        if let Ok(mut st) = app.state.lock() {
+            let raw = windows.iter().map(|w| w.0 as usize).collect();
+            st.peeked = Some((desktop, raw));
+        }
+    }
+
+    /// Move the window last focused on another desktop to this one and focus
+    /// it. Says so with a balloon when no such window is remembered.
+    unsafe fn pull_recent_window(app: &App, app_hwnd: HWND) {
+        let Ok(current) = winvd::get_current_desktop() else {
+            return;
+        };
+        let (Ok(id), Ok(index)) = (current.get_id(), current.get_index()) else {
+            return;
+        };
+        let window = app.switcher.recent_window_elsewhere(id);
+        let Some(window) = window.filter(|&w| w != app_hwnd) else {
+            unsafe {
+                show_balloon(
+                    app,
+                    app_hwnd,
+                    "d_switch",
+                    strings::text(Label::NothingToPull),
+                )
+            };
+            return;
+        };
+        if move_window_to(app, window, index).is_none() {
+            return;
+        }
+        app.switcher.remember_focus(id, window);
+        unsafe {
+            if IsIconic(window).as_bool() {
+                let _ = ShowWindow(window, SW_RESTORE);
+            }
+        }
+        d_switch::set_foreground(window);
+    }
+
+    /// Say what a pin toggle did: the new state in the overlay when
+    /// `show_overlay` is on outside quiet hours, else in a balloon. Failures
+    /// always get a balloon.
+    unsafe fn report_pin(app: &App, app_hwnd: HWND, label: Label, ok: bool) {
+        let overlay = app
+            .state
+            .lock()
+            .map(|st| st.config.show_overlay)
+            .unwrap_or(false);
+        if ok && overlay && !in_quiet_hours(app) {
+            unsafe { overlay::show(strings::text(label)) };
+        } else {
+            unsafe { show_balloon(app, app_hwnd, "d_switch", strings::text(label)) };
+        }
+    }
+
+    /// Pin the foreground window to every desktop, or unpin it if it already is.
+    /// Pinned windows stay focus targets on every desktop. Windows winvd can't
+    /// pin (e.g. system surfaces) get a balloon saying so.
+    unsafe fn toggle_pin_foreground_window(app: &App, app_hwnd: HWND) {
+        let fg = unsafe { GetForegroundWindow() };
+        if fg.0.is_null() || fg == app_hwnd {
+            return;
+        }
+        let Ok(pinned) = winvd::is_pinned_window(fg) else {
+            log::info(&format!("cannot pin {}", unsafe { describe_window(fg) }));
+            unsafe { report_pin(app, app_hwnd, Label::WindowPinFailed, false) };
+            return;
+        };
+        if dry_run(|| {
+            let verb = if pinned { "unpin" } else { "pin" };
+            format!("{} {}", verb, unsafe { describe_window(fg) })
+        }) {
+            return;
+        }
+        let (result, done) = if pinned {
+            (winvd::unpin_window(fg), Label::WindowUnpinned)
+        } else {
+            (winvd::pin_window(fg), Label::WindowPinned)
+        };
+        let text = if result.is_ok() {
+            done
+        } else {
+            Label::WindowPinFailed
+        };
+        unsafe { report_pin(app, app_hwnd, text, result.is_ok()) };
+    }
+
+    /// Like `toggle_pin_foreground_window`, but for all windows of the app.
+    unsafe fn toggle_pin_foreground_app(app: &App, app_hwnd: HWND) {
+        let fg = unsafe { GetForegroundWindow() };
+        if fg.0.is_null() || fg == app_hwnd {
+            return;
+        }
+        // Apps are identified through the owning process; without one there's nothing to pin.
+        if unsafe { window_process_path(fg) }.is_none() {
+            unsafe { show_balloon(app, app_hwnd, "d_switch", strings::text(Label::AppNotFound)) };
+            return;
+        }
+        let Ok(pinned) = winvd::is_pinned_app(fg) else {
+            unsafe { show_balloon(app, app_hwnd, "d_switch", strings::text(Label::AppNotFound)) };
+            return;
+        };
+        if dry_run(|| {
+            let verb = if pinned { "unpin" } else { "pin" };
+            format!("{} the app of {}", verb, unsafe { describe_window(fg) })
+        }) {
+            return;
+        }
+        let (result, done) = if pinned {
+            (winvd::unpin_app(fg), Label::AppUnpinned)
+        } else {
+            (winvd::pin_app(fg), Label::AppPinned)
+        };
+        let text = if result.is_ok() {
+            done
+        } else {
+            Label::AppPinFailed
+        };
+        unsafe { report_pin(app, app_hwnd, text, result.is_ok()) };
+    }
+
+    /// Send the foreground window to `desktop_index` and switch there, leaving
+    /// the moved window in the foreground. Nothing happens if the move fails.
+    unsafe fn move_window_and_follow(app: &App, app_hwnd: HWND, desktop_index: u32) {
+        let Some(moved) = (unsafe { move_foreground_window(app, app_hwnd, desktop_index) }) else {
+            return;
+        };
+
+        // Make the moved window the focus target on the destination desktop.
+        if let Ok(id) = winvd::get_desktop(desktop_index).get_id() {
+            app.switcher.remember_focus(id, moved);
+        }
+        request_switch(app, desktop_index);
+    }
+
+    /// Give the foreground window a desktop of its own: add one at the end, move
+    /// the window there and follow it. Pinned windows get no new desktop.
+    unsafe fn move_window_to_new_desktop(app: &App, app_hwnd: HWND) {
+        let fg = unsafe { GetForegroundWindow() };
+        if fg.0.is_null() || fg == app_hwnd || winvd::is_pinned_window(fg).unwrap_or(false) {
+            return;
+        }
+        if dry_run(|| format!("move {} to a new desktop", unsafe { describe_window(fg) })) {
+            return;
+        }
+        let Ok(index) = winvd::create_desktop().and_then(|d| d.get_index()) else {
+            unsafe {
+                show_balloon(
+                    app,
+                    app_hwnd,
+                    "d_switch",
+                    strings::text(Label::CreateFailed),
+                )
+            };
+            return;
+        };
+        unsafe { move_window_and_follow(app, app_hwnd, index) };
+    }
+
+    /// Draw `text` (the 1-based desktop number) onto a small icon with GDI,
+    /// over `background`.
+    unsafe fn create_number_icon(text: &str, background: COLORREF) -> HICON {
+        // The small icon size follows the display scale (16 at 100%, 32 at 200%).
+        let size = unsafe { GetSystemMetrics(SM_CXSMICON) }.max(16);
+        unsafe {
+            let screen = GetDC(None);
+            let dc = CreateCompatibleDC(screen);
+            let color = CreateCompatibleBitmap(screen, size, size);
+            // All-zero AND mask: every pixel of the color bitmap is opaque.
+            let mask_bits = vec![0u8; (size as usize).div_ceil(16) * 2 * size as usize];
+            let mask = CreateBitmap(size, size, 1, 1, Some(mask_bits.as_ptr().cast()));
+
+            let old_bitmap = SelectObject(dc, color);
+            let mut rect = RECT {
+                left: 0,
+                top: 0,
+                right: size,
+                bottom: size,
+            };
+            let brush = CreateSolidBrush(background);
+            FillRect(dc, &rect, brush);
+            let _ = DeleteObject(brush);
+
+            let face = wstr("Segoe UI");
+            let font = CreateFontW(
+                -(size * 7 / 8),
+                0,
+                0,
+                0,
+                FW_BOLD.0 as i32,
+                0,
+                0,
+                0,
+                DEFAULT_CHARSET.0 as u32,
+                OUT_DEFAULT_PRECIS.0 as u32,
+                CLIP_DEFAULT_PRECIS.0 as u32,
+                NONANTIALIASED_QUALITY.0 as u32,
+                (DEFAULT_PITCH.0 | FF_DONTCARE.0) as u32,
+                PCWSTR(face.as_ptr()),
+            );
+            let old_font = SelectObject(dc, font);
+            SetBkMode(dc, TRANSPARENT);
+            // Dark digits on light backgrounds, so a pale colour stays readable.
+            let [r, g, b, _] = background.0.to_le_bytes();
+            let luma = 299 * r as u32 + 587 * g as u32 + 114 * b as u32;
+            let text_color = if luma > 150_000 {
+                0x0000_0000
+            } else {
+                0x00FF_FFFF
+            };
+            SetTextColor(dc, COLORREF(text_color));
+            let mut label: Vec<u16> = OsStr::new(text).encode_wide().collect();
+            DrawTextW(
+                dc,
+                &mut label,
+                &mut rect,
+                DT_CENTER | DT_VCENTER | DT_SINGLELINE,
+            );
+            SelectObject(dc, old_font);
+            SelectObject(dc, old_bitmap);
+            let _ = DeleteObject(font);
+
+            let info = ICONINFO {
+                fIcon: BOOL(1),
+                xHotspot: 0,
+                yHotspot: 0,
+                hbmMask: mask,
+                hbmColor: color,
+            };
+            let hicon = CreateIconIndirect(&info).unwrap_or(HICON(null_mut()));
+
+            // CreateIconIndirect copies the bitmaps.
+            let _ = DeleteObject(mask);
+            let _ = DeleteObject(color);
+            let _ = DeleteDC(dc);
+            ReleaseDC(None, screen);
+            hicon
+        }
+    }
+
+    /// "#rrggbb" as a GDI colour.
+    fn parse_color(text: &str) -> Option<COLORREF> {
+        let hex = text.trim().strip_prefix('#')?;
+        if hex.len() != 6 {
+            return None;
+        }
+        let rgb = u32::from_str_radix(hex, 16).ok()?;
+        let [b, g, r, _] = rgb.to_le_bytes();
+        Some(COLORREF(u32::from_le_bytes([r, g, b, 0])))
+    }
+
+    /// The tray icon background for a desktop: its `desktop_colors` entry, or grey.
+    fn icon_background(app: &App, desktop_index: Option<u32>) -> COLORREF {
+        let color = desktop_index.and_then(|i| {
+            let st = app.state.lock().ok()?;
+            parse_color(st.config.desktop_colors.get(i as usize)?)
+        });
+        color.unwrap_or(COLORREF(0x0030_3030))
+    }
+
+    /// How desktop `index` is shown in the menu, tooltip, overlay and picker:
+    /// "Desktop 3: Work" when it has a name, else "Desktop 3" or its
+    /// `name_template`.
+    fn desktop_display_name(app: &App, index: u32, name: &str) -> String {
+        desktop_label(app, &(index + 1).to_string(), name)
+    }
+
+    /// [`desktop_display_name`] with the number given as text, so the menu can
+    /// put its mnemonic in.
+    fn desktop_label(app: &App, number: &str, name: &str) -> String {
+        if !name.is_empty() {
+            return strings::format_all(Label::NamedDesktop, &[&number, &name]);
+        }
+        let template = app
+            .state
+            .lock()
+            .ok()
+            .and_then(|st| st.config.name_template.clone());
+        match template {
+            Some(template) => template.replace("{index}", number),
+            None => strings::format(Label::Desktop, number),
+        }
+    }
+
+    fn icon_label(desktop_index: Option<u32>) -> String {
+        match desktop_index {
+            Some(i) => (i + 1).to_string(),
+            None => "?".to_string(),
+        }
+    }
+
+    /// Swap the stored tray icon for `hicon`, destroying the previous one.
+    unsafe fn replace_tray_icon_handle(app: &App, hicon: HICON) {
+        let old = app
+            .state
+            .lock()
+            .map(|mut st| std::mem::replace(&mut st.tray_icon, hicon.0 as usize))
+            .unwrap_or(0);
+        if old != 0 {
+            let _ = unsafe { DestroyIcon(HICON(old as *mut core::ffi::c_void)) };
+        }
+    }
+
+    unsafe fn add_tray_icon(app: &App, hwnd: HWND, desktop_index: Option<u32>) {
+        let hicon = unsafe {
+            create_number_icon(
+                &icon_label(desktop_index),
+                icon_background(app, desktop_index),
+            )
+        };
+
+        let mut nid = NOTIFYICONDATAW {
+            cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
+            hWnd: hwnd,
+            uID: 1,
+            // Version 4 hides the standard tooltip unless NIF_SHOWTIP is set.
+            uFlags: NIF_MESSAGE | NIF_ICON | NIF_TIP | NIF_SHOWTIP,
+            uCallbackMessage: WM_TRAYICON,
+            hIcon: hicon,
+            ..Default::default()
+        };
+
+        copy_to_wide_buf(&mut nid.szTip, &tray_tip(app, desktop_index));
+
+        unsafe {
+            // If the icon is somehow still there, refresh it instead of adding a second one.
+            if !Shell_NotifyIconW(NIM_ADD, &nid).as_bool() {
+                let _ = Shell_NotifyIconW(NIM_MODIFY, &nid);
+            }
+            // Version 4 callbacks: the event is in the low word of lParam
+            // (see WM_TRAYICON in wndproc), and right clicks come as WM_CONTEXTMENU.
+            nid.Anonymous.uVersion = NOTIFYICON_VERSION_4;
+            let _ = Shell_NotifyIconW(NIM_SETVERSION, &nid);
+            replace_tray_icon_handle(app, hicon);
+        }
+    }
+
+    /// Redraw the tray icon and tooltip for the given (0-based) desktop index.
+    unsafe fn update_tray_icon(app: &App, hwnd: HWND, desktop_index: u32) {
+        let hicon = unsafe {
+            create_number_icon(
+                &icon_label(Some(desktop_index)),
+                icon_background(app, Some(desktop_index)),
+            )
+        };
+
+        let mut nid = NOTIFYICONDATAW {
+            cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
+            hWnd: hwnd,
+            uID: 1,
+            uFlags: NIF_ICON | NIF_TIP | NIF_SHOWTIP,
+            hIcon: hicon,
+            ..Default::default()
+        };
+        // Long names are cut to fit the 128-unit szTip, keeping its NUL.
+        copy_to_wide_buf(&mut nid.szTip, &tray_tip(app, Some(desktop_index)));
+
+        unsafe {
+            let _ = Shell_NotifyIconW(NIM_MODIFY, &nid);
+            replace_tray_icon_handle(app, hicon);
+        }
+    }
+
+    /// The configured `tray_tooltip` filled in. `{desktop}` is the desktop's
+    /// [`desktop_display_name`].
+    fn tray_tip(app: &App, desktop_index: Option<u32>) -> String {
+        let desktop = match desktop_index {
+            Some(i) => {
+                let name = winvd::get_desktop(i).get_name().unwrap_or_default();
+                desktop_display_name(app, i, &name)
+            }
+            None => "d_switch".to_string(),
+        };
+        let (template, hotkeys) = app
+            .state
+            .lock()
+            .map(|st| {
+                let mut ids: Vec<i32> = st.hotkeys.keys().copied().collect();
+                ids.sort_unstable();
+                let labels: Vec<String> = ids
+                    .iter()
+                    .filter_map(|&id| hotkey_binding(&st.config, id))
+                    .map(binding_label)
+                    .collect();
+                (st.config.tray_tooltip.clone(), labels.join(", "))
+            })
+            .unwrap_or_default();
+        let mut tip = template
+            .replace("{desktop}", &desktop)
+            .replace("{hotkeys}", &hotkeys);
+        if hotkeys_paused(app) {
+            tip.push_str(strings::text(Label::Paused));
+        }
+        tip
+    }
+
+    unsafe fn update_tray_tip(app: &App, hwnd: HWND) {
+        let mut nid = NOTIFYICONDATAW {
+            cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
+            hWnd: hwnd,
+            uID: 1,
+            uFlags: NIF_TIP | NIF_SHOWTIP,
+            ..Default::default()
+        };
+        let current = winvd::get_current_desktop()
+            .and_then(|d| d.get_index())
+            .ok();
+        copy_to_wide_buf(&mut nid.szTip, &tray_tip(app, current));
+        unsafe {
+            let _ = Shell_NotifyIconW(NIM_MODIFY, &nid);
+        }
+    }
+
+    /// Copy `s` into a fixed-size UTF-16 buffer, truncating and always leaving
+    /// a terminating NUL.
+    fn copy_to_wide_buf(dst: &mut [u16], s: &str) {
+        let Some(max) = dst.len().checked_sub(1) else {
+            return;
+        };
+        let mut n = 0;
+        for unit in OsStr::new(s).encode_wide().take(max) {
+            dst[n] = unit;
+            n += 1;
+        }
+        // Don't leave half of a surrogate pair at the cut.
+        if n == max && n > 0 && (0xD800..0xDC00).contains(&dst[n - 1]) {
+            n -= 1;
+        }
+        dst[n] = 0;
+    }
+
+    /// `%WINDIR%\Media\Windows Navigation Start.wav`, Explorer's navigation click.
+    fn default_switch_sound() -> PathBuf {
+        let windir = std::env::var_os("WINDIR").unwrap_or_else(|| "C:\\Windows".into());
+        PathBuf::from(windir)
+            .join("Media")
+            .join("Windows Navigation Start.wav")
+    }
+
+    /// Start playing a .wav without waiting for it. A missing or unplayable
+    /// file is silently ignored.
+    unsafe fn play_sound(path: &Path) {
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain(once(0)).collect();
+        let _ = unsafe {
+            PlaySoundW(
+                PCWSTR(wide.as_ptr()),
+                None,
+                SND_ASYNC | SND_FILENAME | SND_NODEFAULT,
+            )
+        };
+    }
+
+    /// Whether the local time is inside the configured `quiet_hours`.
+    fn in_quiet_hours(app: &App) -> bool {
+        let Some(quiet) = app
+            .state
+            .lock()
+            .ok()
+            .and_then(|st| st.config.quiet_hours.clone())
+        else {
+            return false;
+        };
+        let now = unsafe { GetLocalTime() };
+        quiet.contains(now.wHour as u32 * 60 + now.wMinute as u32)
+    }
+
+    /// Pop up a balloon notification from the tray icon, unless it's quiet hours.
+    unsafe fn show_balloon(app: &App, hwnd: HWND, title: &str, text: &str) {
+        if in_quiet_hours(app) {
+            log::debug(&format!("quiet hours, no balloon: {}", text));
+            return;
+        }
+        let mut nid = NOTIFYICONDATAW {
+            cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
+            hWnd: hwnd,
+            uID: 1,
+            uFlags: NIF_INFO,
+            dwInfoFlags: NIIF_WARNING,
+            ..Default::default()
+        };
+        copy_to_wide_buf(&mut nid.szInfoTitle, title);
+        copy_to_wide_buf(&mut nid.szInfo, text);
+        unsafe {
+            let _ = Shell_NotifyIconW(NIM_MODIFY, &nid);
+        }
+    }
+
+    unsafe fn remove_tray_icon(hwnd: HWND) {
+        let nid = NOTIFYICONDATAW {
+            cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
+            hWnd: hwnd,
+            uID: 1,
+            ..Default::default()
+        };
+        unsafe {
+            let _ = Shell_NotifyIconW(NIM_DELETE, &nid);
+        }
+    }
+
+    unsafe fn tray_icon_rect(hwnd: HWND) -> Option<RECT> {
+        let id = NOTIFYICONIDENTIFIER {
+            cbSize: std::mem::size_of::<NOTIFYICONIDENTIFIER>() as u32,
+            hWnd: hwnd,
+            uID: 1,
+            ..Default::default()
+        };
+        unsafe { Shell_NotifyIconGetRect(&id) }.ok()
+    }
+
+    /// The tray callback carries no wheel delta, so while the cursor hovers the
+    /// icon a low-level mouse hook picks up the wheel instead.
+    unsafe fn start_tray_wheel_hook(app: &App, hwnd: HWND) {
+        let Some(rect) = (unsafe { tray_icon_rect(hwnd) }) else {
+            return;
+        };
+        let Ok(mut st) = app.state.lock() else {
+            return;
+        };
+        st.tray_hover_rect = Some(rect);
+        if st.wheel_hook != 0 {
+            return;
+        }
+        let Ok(hinstance) = (unsafe { GetModuleHandleW(PCWSTR(null())) }) else {
+            return;
+        };
+        if let Ok(hook) =
+            unsafe { SetWindowsHookExW(WH_MOUSE_LL, Some(tray_wheel_proc), hinstance, 0) }
+        {
+            st.wheel_hook = hook.0 as usize;
+            st.wheel_accum = 0;
+            unsafe { SetTimer(hwnd, TIMER_TRAY_HOVER, 100, None) };
+        }
+    }
+
+    unsafe fn stop_tray_wheel_hook(app: &App, hwnd: HWND) {
+        let hook = app
+            .state
+            .lock()
+            .map(|mut st| {
+                st.tray_hover_rect = None;
+                std::mem::take(&mut st.wheel_hook)
+            })
+            .unwrap_or(0);
+        if hook != 0 {
+            unsafe {
+                let _ = UnhookWindowsHookEx(HHOOK(hook as *mut core::ffi::c_void));
+                let _ = KillTimer(hwnd, TIMER_TRAY_HOVER);
+            }
+        }
+    }
+
+    unsafe extern "system" fn tray_wheel_proc(
+        code: i32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        if code >= 0
+            && wparam.0 as u32 == WM_MOUSEWHEEL
+            && let Some(app) = unsafe { hook_app() }
+        {
+            let info = unsafe { &*(lparam.0 as *const MSLLHOOKSTRUCT) };
+            let delta = (info.mouseData >> 16) as u16 as i16 as i32;
+
+            let step = app.state.lock().ok().and_then(|mut st| {
+                let rect = st.tray_hover_rect?;
+                if !unsafe { PtInRect(&rect, info.pt) }.as_bool() {
+                    return None;
+                }
+                st.wheel_accum += delta;
+                if st.wheel_accum.unsigned_abs() < WHEEL_DELTA {
+                    // Swallow partial high-resolution deltas until a full notch adds up.
+                    return Some(None);
+                }
+                let forward = st.wheel_accum < 0;
+                st.wheel_accum = 0;
+                let now = Instant::now();
+                if st
+                    .last_wheel_step
+                    .is_some_and(|t| now.duration_since(t) < WHEEL_DEBOUNCE)
+                {
+                    return Some(None);
+                }
+                st.last_wheel_step = Some(now);
+                Some(Some(forward))
+            });
+
+            if let Some(step) = step {
+                // Wheel up = previous desktop, wheel down = next.
+                if let Some(forward) = step {
+                    switch_relative(&app, forward);
+                }
+                // Over our icon: keep the wheel from reaching the taskbar.
+                return LRESULT(1);
+            }
+        }
+        unsafe { CallNextHookEx(None, code, wparam, lparam) }
+    }
+
+    /// Show the desktop grid and switch to the pick, or with Shift held, send
+    /// the window that was in front there instead. On cancel, focus goes back
+    /// to the window that had it before the grid took it. Does nothing while a
+    /// grid is already up.
+    unsafe fn pick_desktop(app: &App, app_hwnd: HWND) {
+        if picker::is_active() {
+            return;
+        }
+        let Ok(desktops) = winvd::get_desktops() else {
+            return;
+        };
+        let names: Vec<String> = desktops
+            .iter()
+            .zip(0..)
+            .map(|(d, index)| {
+                let name = d.get_name().unwrap_or_default();
+                desktop_display_name(app, index, &name)
+            })
+            .collect();
+        let current = winvd::get_current_desktop()
+            .and_then(|d| d.get_index())
+            .unwrap_or(0);
+        let previous = unsafe { GetForegroundWindow() };
+        let target = unsafe { window_to_move(app_hwnd) };
+        match unsafe { picker::pick_desktop(&names, current) } {
+            Some(index) if shift_held() => {
+                if let Some(window) = target {
+                    let _ = move_window_to(app, window, index);
+                }
+            }
+            Some(index) => request_switch(app, index),
+            None => {
+                if !previous.is_invalid() {
+                    d_switch::set_foreground(previous);
+                }
+            }
+        }
+    }
+
+    /// Start collecting digits for a goto. The keyboard hook is only installed
+    /// while the mode is active.
+    unsafe fn start_goto_mode(app: &App, hwnd: HWND) {
+        let Ok(mut st) = app.state.lock() else {
+            return;
+        };
+        if st.goto_mode.is_some() {
+            return;
+        }
+        let Ok(hinstance) = (unsafe { GetModuleHandleW(PCWSTR(null())) }) else {
+            return;
+        };
+        match unsafe { SetWindowsHookExW(WH_KEYBOARD_LL, Some(goto_key_proc), hinstance, 0) } {
+            Ok(hook) => {
+                st.goto_mode = Some(GotoMode {
+                    hook: hook.0 as usize,
+                    hwnd: hwnd.0 as usize,
+                    digits: String::new(),
+                });
+                unsafe { SetTimer(hwnd, TIMER_GOTO, GOTO_TIMEOUT_MS, None) };
+            }
+            Err(e) => log::error(&format!("goto mode: keyboard hook failed: {}", e)),
+        }
+    }
+
+    /// Leave goto mode; with `commit`, switch to the typed desktop (1-based).
+    unsafe fn end_goto_mode(app: &App, commit: bool) {
+        let Some(mode) = app.state.lock().ok().and_then(|mut st| st.goto_mode.take()) else {
+            return;
+        };
+        unsafe {
+            let _ = UnhookWindowsHookEx(HHOOK(mode.hook as *mut core::ffi::c_void));
+            let _ = KillTimer(HWND(mode.hwnd as *mut core::ffi::c_void), TIMER_GOTO);
         }
-
-        if unsafe { IsIconic(hwnd) }.as_bool() {
-            let _ = unsafe { ShowWindow(hwnd, SW_RESTORE) };
+        if commit && let Ok(number @ 1..) = mode.digits.parse::<u32>() {
+            request_switch(app, number - 1);
         }
-        let _ = unsafe { SetForegroundWindow(hwnd) };
     }
 
-    unsafe fn try_switch_desktop(app_hwnd: HWND, desktop_index: u32) {
-        unsafe { save_focus_for_current_desktop(app_hwnd) };
+    enum GotoKey {
+        Digit(char),
+        Commit,
+        Cancel,
+        // Modifiers, e.g. the Alt of Alt+G still held; no effect on the mode.
+        Modifier,
+        Other,
+    }
 
-        let Ok(count) = winvd::get_desktop_count() else {
-            return;
-        };
-        if desktop_index >= count {
-            return;
+    fn classify_goto_key(vk: u32) -> GotoKey {
+        let vk = VIRTUAL_KEY(vk as u16);
+        if (VK_0.0..=VK_0.0 + 9).contains(&vk.0) {
+            return GotoKey::Digit(char::from(b'0' + (vk.0 - VK_0.0) as u8));
         }
-        if winvd::switch_desktop(desktop_index).is_err() {
-            return;
+        if (VK_NUMPAD0.0..=VK_NUMPAD0.0 + 9).contains(&vk.0) {
+            return GotoKey::Digit(char::from(b'0' + (vk.0 - VK_NUMPAD0.0) as u8));
         }
+        match vk {
+            VK_RETURN => GotoKey::Commit,
+            VK_ESCAPE => GotoKey::Cancel,
+            VK_SHIFT | VK_LSHIFT | VK_RSHIFT | VK_CONTROL | VK_LCONTROL | VK_RCONTROL | VK_MENU
+            | VK_LMENU | VK_RMENU | VK_LWIN | VK_RWIN => GotoKey::Modifier,
+            _ => GotoKey::Other,
+        }
+    }
 
-        // Switching can be async; retry briefly until the desktop becomes current.
-        for _ in 0..12 {
-            let on_target = winvd::get_current_desktop()
-                .and_then(|d| d.get_index())
-                .map(|i| i == desktop_index)
-                .unwrap_or(true);
-
-            if on_target {
-                break;
+    /// Digits, Enter and Esc are swallowed while in goto mode; any other key
+    /// ends the mode and goes through as usual.
+    unsafe extern "system" fn goto_key_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        if code >= 0
+            && let Some(app) = unsafe { hook_app() }
+        {
+            let info = unsafe { &*(lparam.0 as *const KBDLLHOOKSTRUCT) };
+            let down = matches!(wparam.0 as u32, WM_KEYDOWN | WM_SYSKEYDOWN);
+            match classify_goto_key(info.vkCode) {
+                GotoKey::Digit(digit) => {
+                    if down
+                        && let Ok(mut st) = app.state.lock()
+                        && let Some(mode) = st.goto_mode.as_mut()
+                    {
+                        if mode.digits.len() < GOTO_MAX_DIGITS {
+                            mode.digits.push(digit);
+                        }
+                        // Restarts the timeout.
+                        let hwnd = HWND(mode.hwnd as *mut core::ffi::c_void);
+                        unsafe { SetTimer(hwnd, TIMER_GOTO, GOTO_TIMEOUT_MS, None) };
+                    }
+                    return LRESULT(1);
+                }
+                GotoKey::Commit | GotoKey::Cancel if !down => return LRESULT(1),
+                GotoKey::Commit => {
+                    unsafe { end_goto_mode(&app, true) };
+                    return LRESULT(1);
+                }
+                GotoKey::Cancel => {
+                    unsafe { end_goto_mode(&app, false) };
+                    return LRESULT(1);
+                }
+                GotoKey::Modifier => {}
+                GotoKey::Other => {
+                    if down {
+                        unsafe { end_goto_mode(&app, false) };
+                    }
+                }
             }
-            std::thread::sleep(Duration::from_millis(15));
         }
-
-        unsafe { restore_focus_for_desktop(desktop_index) };
+        unsafe { CallNextHookEx(None, code, wparam, lparam) }
     }
 
-    unsafe fn add_tray_icon(hwnd: HWND, hicon: HICON) {
-        let mut nid = NOTIFYICONDATAW::default();
-        nid.cbSize = std::mem::size_of::<NOTIFYICONDATAW>() as u32;
-        nid.hWnd = hwnd;
-        nid.uID = 1;
-        nid.uFlags = NIF_MESSAGE | NIF_ICON | NIF_TIP;
-        nid.uCallbackMessage = WM_TRAYICON;
-        nid.hIcon = hicon;
-
-        let tip = wstr("d_switch (Alt+1..9)");
-        let tip_len = nid.szTip.len().min(tip.len());
-        nid.szTip[..tip_len].copy_from_slice(&tip[..tip_len]);
+    /// Whether our value exists under HKCU\...\CurrentVersion\Run.
+    fn run_at_startup_enabled() -> bool {
+        let key = wstr(RUN_KEY);
+        let value = wstr(RUN_VALUE);
+        let err = unsafe {
+            RegGetValueW(
+                HKEY_CURRENT_USER,
+                PCWSTR(key.as_ptr()),
+                PCWSTR(value.as_ptr()),
+                RRF_RT_REG_SZ,
+                None,
+                None,
+                None,
+            )
+        };
+        err == ERROR_SUCCESS
+    }
 
-        unsafe {
-            let _ = Shell_NotifyIconW(NIM_ADD, &nid);
+    /// Write (quoted current exe path) or delete the Run value.
+    fn set_run_at_startup(enabled: bool) {
+        let key = wstr(RUN_KEY);
+        let value = wstr(RUN_VALUE);
+        if !enabled {
+            let _ = unsafe {
+                RegDeleteKeyValueW(
+                    HKEY_CURRENT_USER,
+                    PCWSTR(key.as_ptr()),
+                    PCWSTR(value.as_ptr()),
+                )
+            };
+            return;
         }
+
+        let Ok(exe) = std::env::current_exe() else {
+            return;
+        };
+        // Quoted so paths with spaces aren't split by the shell.
+        let command = wstr(&format!("\"{}\"", exe.display()));
+        let _ = unsafe {
+            RegSetKeyValueW(
+                HKEY_CURRENT_USER,
+                PCWSTR(key.as_ptr()),
+                PCWSTR(value.as_ptr()),
+                REG_SZ.0,
+                Some(command.as_ptr().cast()),
+                (command.len() * std::mem::size_of::<u16>()) as u32,
+            )
+        };
     }
 
-    unsafe fn remove_tray_icon(hwnd: HWND) {
-        let mut nid = NOTIFYICONDATAW::default();
-        nid.cbSize = std::mem::size_of::<NOTIFYICONDATAW>() as u32;
-        nid.hWnd = hwnd;
-        nid.uID = 1;
-        unsafe {
-            let _ = Shell_NotifyIconW(NIM_DELETE, &nid);
-        }
+    /// Desktop index (0-based) of a "Desktop N" menu command; `None` for any
+    /// other command.
+    fn menu_id_to_desktop(cmd: usize) -> Option<u32> {
+        (MENU_DESKTOP_FIRST..=MENU_DESKTOP_LAST)
+            .contains(&cmd)
+            .then(|| (cmd - MENU_DESKTOP_FIRST) as u32)
     }
 
-    unsafe fn show_tray_menu(hwnd: HWND) {
+    unsafe fn show_tray_menu(app: &App, hwnd: HWND) {
         unsafe {
             let menu = CreatePopupMenu().unwrap_or(HMENU(null_mut()));
             if menu.0.is_null() {
                 return;
             }
 
-            // Optional direct desktop entries (still "source code as config").
-            for i in HOTKEY_FIRST..=HOTKEY_LAST {
-                let label = wstr(&format!("Desktop {}\tAlt+{}", i, i));
-                let _ = AppendMenuW(menu, MF_STRING, i as usize, PCWSTR(label.as_ptr()));
+            let count = winvd::get_desktop_count().unwrap_or(0);
+            let header = wstr(&match count {
+                1 => strings::text(Label::OneDesktop).to_string(),
+                n => strings::format(Label::DesktopCount, n),
+            });
+            let _ = AppendMenuW(menu, MF_STRING | MF_GRAYED, 0, PCWSTR(header.as_ptr()));
+            let _ = AppendMenuW(menu, MF_SEPARATOR, 0, PCWSTR(null()));
+
+            // Every desktop by name, the current one checked, with its hotkey if
+            // one is bound.
+            let combos: HashMap<u32, String> = app
+                .state
+                .lock()
+                .map(|st| {
+                    st.config
+                        .bindings
+                        .iter()
+                        .rev()
+                        .filter(|b| b.action == Action::Switch)
+                        .map(|b| (b.desktop, binding_label(b)))
+                        .collect()
+                })
+                .unwrap_or_default();
+            let desktops_menu = CreatePopupMenu().unwrap_or(HMENU(null_mut()));
+            if !desktops_menu.0.is_null() {
+                let current = winvd::get_current_desktop()
+                    .and_then(|d| d.get_index())
+                    .ok();
+                let desktops = winvd::get_desktops().unwrap_or_default();
+                let ids = MENU_DESKTOP_FIRST..=MENU_DESKTOP_LAST;
+                for (id, desktop) in ids.zip(&desktops) {
+                    let Some(index) = menu_id_to_desktop(id) else {
+                        break;
+                    };
+                    let number = index + 1;
+                    // Desktops 1..9 get their digit as the menu mnemonic.
+                    let shown = if number <= 9 {
+                        format!("&{}", number)
+                    } else {
+                        number.to_string()
+                    };
+                    let name = desktop.get_name().unwrap_or_default();
+                    // A `&` in the name or template itself isn't a mnemonic.
+                    let mut label = desktop_label(app, "{index}", &name)
+                        .replace('&', "&&")
+                        .replace("{index}", &shown);
+                    if let Some(combo) = combos.get(&index) {
+                        label = format!("{}\t{}", label, combo);
+                    }
+                    let flags = if current == Some(index) {
+                        MF_STRING | MF_CHECKED
+                    } else {
+                        MF_STRING
+                    };
+                    let label = wstr(&label);
+                    let _ = AppendMenuW(desktops_menu, flags, id, PCWSTR(label.as_ptr()));
+                }
+                let title = wstr(strings::text(Label::Desktops));
+                let _ = AppendMenuW(
+                    menu,
+                    MF_POPUP,
+                    desktops_menu.0 as usize,
+                    PCWSTR(title.as_ptr()),
+                );
+            }
+
+            let _ = AppendMenuW(menu, MF_SEPARATOR, 0, PCWSTR(null()));
+            let new_desktop = wstr(strings::text(Label::NewDesktop));
+            let _ = AppendMenuW(
+                menu,
+                MF_STRING,
+                MENU_NEW_DESKTOP_ID,
+                PCWSTR(new_desktop.as_ptr()),
+            );
+            let target = app
+                .state
+                .lock()
+                .map(|st| st.config.ensure_desktop_count)
+                .unwrap_or(0);
+            if target > 1 {
+                let ensure_flags = if count >= target {
+                    MF_STRING | MF_GRAYED
+                } else {
+                    MF_STRING
+                };
+                let ensure = wstr(&strings::format(Label::EnsureDesktops, target));
+                let _ = AppendMenuW(
+                    menu,
+                    ensure_flags,
+                    MENU_ENSURE_DESKTOPS_ID,
+                    PCWSTR(ensure.as_ptr()),
+                );
             }
+            let remove_desktop = wstr(strings::text(Label::RemoveDesktop));
+            let _ = AppendMenuW(
+                menu,
+                MF_STRING,
+                MENU_REMOVE_DESKTOP_ID,
+                PCWSTR(remove_desktop.as_ptr()),
+            );
+            let rename_desktop = wstr(strings::text(Label::RenameDesktop));
+            let _ = AppendMenuW(
+                menu,
+                MF_STRING,
+                MENU_RENAME_DESKTOP_ID,
+                PCWSTR(rename_desktop.as_ptr()),
+            );
+            let merge = wstr(strings::text(Label::MergeDesktops));
+            let _ = AppendMenuW(menu, MF_STRING, MENU_MERGE_ID, PCWSTR(merge.as_ptr()));
 
             let _ = AppendMenuW(menu, MF_SEPARATOR, 0, PCWSTR(null()));
-            let exit = wstr("Exit");
+            let hotkeys_flags = if hotkeys_paused(app) {
+                MF_STRING
+            } else {
+                MF_STRING | MF_CHECKED
+            };
+            let hotkeys = wstr(strings::text(Label::HotkeysEnabled));
+            let _ = AppendMenuW(
+                menu,
+                hotkeys_flags,
+                MENU_HOTKEYS_ID,
+                PCWSTR(hotkeys.as_ptr()),
+            );
+            let follow_flags = if follow_on_move(app) {
+                MF_STRING | MF_CHECKED
+            } else {
+                MF_STRING
+            };
+            let follow = wstr(strings::text(Label::FollowOnMove));
+            let _ = AppendMenuW(menu, follow_flags, MENU_FOLLOW_ID, PCWSTR(follow.as_ptr()));
+            let settings = wstr(strings::text(Label::Settings));
+            let _ = AppendMenuW(menu, MF_STRING, MENU_SETTINGS_ID, PCWSTR(settings.as_ptr()));
+            let reload = wstr(strings::text(Label::ReloadConfig));
+            let _ = AppendMenuW(menu, MF_STRING, MENU_RELOAD_ID, PCWSTR(reload.as_ptr()));
+            // Read fresh each time: the value may have been removed elsewhere.
+            let startup_flags = if run_at_startup_enabled() {
+                MF_STRING | MF_CHECKED
+            } else {
+                MF_STRING
+            };
+            let startup = wstr(strings::text(Label::RunAtStartup));
+            let _ = AppendMenuW(
+                menu,
+                startup_flags,
+                MENU_STARTUP_ID,
+                PCWSTR(startup.as_ptr()),
+            );
+            let about = wstr(strings::text(Label::About));
+            let _ = AppendMenuW(menu, MF_STRING, MENU_ABOUT_ID, PCWSTR(about.as_ptr()));
+            let exit = wstr(strings::text(Label::Exit));
             let _ = AppendMenuW(menu, MF_STRING, MENU_EXIT_ID, PCWSTR(exit.as_ptr()));
 
+            let menu_window = window_to_move(hwnd).map_or(0, |w| w.0 as usize);
+            if let Ok(mut st) = app.state.lock() {
+                st.menu_window = menu_window;
+            }
+
             let mut pt = windows::Win32::Foundation::POINT::default();
             let _ = GetCursorPos(&mut pt);
             let _ = SetForegroundWindow(hwnd);
@@ -195,76 +2640,782 @@ mod app {
                 hwnd,
                 None,
             );
+            // The menu is rebuilt on every open; free it so USER handles don't leak.
+            // This takes the Desktops submenu with it.
+            let _ = DestroyMenu(menu);
         }
     }
 
-    unsafe fn register_hotkeys(hwnd: HWND) {
-        for id in HOTKEY_FIRST..=HOTKEY_LAST {
-            let vk = (VK_1.0 + (id - 1) as u16) as u32;
-            // MOD_NOREPEAT: prevent repeats while holding keys.
-            unsafe {
-                let mods: HOT_KEY_MODIFIERS = MOD_ALT | MOD_NOREPEAT;
-                let _ = RegisterHotKey(hwnd, id, mods, vk);
+    /// Virtual-key code for a config key name: "0".."9", "A".."Z", "F1".."F24",
+    /// "Left", "Right", "Home", "End", "`", "[", "]" or "Pause".
+    pub(crate) fn parse_key(key: &str) -> Option<u32> {
+        let key = key.trim().to_ascii_uppercase();
+        let named = match key.as_str() {
+            "LEFT" => Some(VK_LEFT),
+            "RIGHT" => Some(VK_RIGHT),
+            "HOME" => Some(VK_HOME),
+            "END" => Some(VK_END),
+            "`" => Some(VK_OEM_3),
+            "[" => Some(VK_OEM_4),
+            "]" => Some(VK_OEM_6),
+            "PAUSE" => Some(VK_PAUSE),
+            _ => None,
+        };
+        if let Some(vk) = named {
+            return Some(vk.0 as u32);
+        }
+        let bytes = key.as_bytes();
+        if let [c @ b'0'..=b'9'] = bytes {
+            return Some((VK_0.0 + (c - b'0') as u16) as u32);
+        }
+        if let [c @ b'A'..=b'Z'] = bytes {
+            return Some((VK_A.0 + (c - b'A') as u16) as u32);
+        }
+        if let Some(n) = key.strip_prefix("NUMPAD") {
+            let n: u16 = n.parse().ok()?;
+            return (n <= 9).then(|| (VK_NUMPAD0.0 + n) as u32);
+        }
+        let n: u16 = key.strip_prefix('F')?.parse().ok()?;
+        if (1..=24).contains(&n) {
+            return Some((VK_F1.0 + n - 1) as u32);
+        }
+        None
+    }
+
+    /// Combined modifiers for a binding. `None` if any name is unknown.
+    fn parse_modifiers(names: &[String]) -> Option<HOT_KEY_MODIFIERS> {
+        let mut mods = HOT_KEY_MODIFIERS(0);
+        for name in names {
+            mods |= match name.trim().to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => MOD_CONTROL,
+                "alt" => MOD_ALT,
+                "shift" => MOD_SHIFT,
+                "win" => MOD_WIN,
+                _ => return None,
+            };
+        }
+        Some(mods)
+    }
+
+    /// Human-readable combination, e.g. "Ctrl+Alt+3".
+    pub(crate) fn binding_label(binding: &Binding) -> String {
+        let mut parts: Vec<String> = Vec::new();
+        for name in ["ctrl", "alt", "shift", "win"] {
+            if binding
+                .modifiers
+                .iter()
+                .any(|m| m.trim().eq_ignore_ascii_case(name))
+            {
+                let mut cap = name.to_string();
+                cap[..1].make_ascii_uppercase();
+                parts.push(cap);
+            }
+        }
+        parts.push(binding.key.trim().to_ascii_uppercase());
+        parts.join("+")
+    }
+
+    /// Hotkey id for the binding at `index`; ids are 1-based positions in the
+    /// bindings list, as RegisterHotKey wants them nonzero.
+    fn binding_hotkey_id(index: usize) -> i32 {
+        index as i32 + 1
+    }
+
+    /// The binding a hotkey id was registered for; `None` for ids that
+    /// [`binding_hotkey_id`] never hands out.
+    fn hotkey_binding(config: &Config, id: i32) -> Option<&Binding> {
+        let index = usize::try_from(id).ok()?.checked_sub(1)?;
+        config.bindings.get(index)
+    }
+
+    /// Win plus a number-row digit without Shift: the combination Windows uses
+    /// to launch the matching taskbar app.
+    fn is_win_digit(binding: &Binding) -> bool {
+        let has = |name: &str| {
+            binding
+                .modifiers
+                .iter()
+                .any(|m| m.trim().eq_ignore_ascii_case(name))
+        };
+        let key = binding.key.trim();
+        has("win") && !has("shift") && key.len() == 1 && key.as_bytes()[0].is_ascii_digit()
+    }
+
+    /// The key and modifiers each binding registers with; `None` where it
+    /// doesn't parse. With `win_number_needs_shift`, Win+digit bindings take
+    /// Shift, and a binding already on that combination (Move, by default)
+    /// takes Ctrl as well, so both keep working.
+    fn hotkey_combos(config: &Config) -> Vec<Option<(u32, HOT_KEY_MODIFIERS)>> {
+        let bindings = &config.bindings;
+        let mut combos: Vec<_> = bindings
+            .iter()
+            .map(|b| Some((parse_key(&b.key)?, parse_modifiers(&b.modifiers)?)))
+            .collect();
+        if !config.win_number_needs_shift {
+            return combos;
+        }
+        let shifted: Vec<usize> = (0..bindings.len())
+            .filter(|&i| combos[i].is_some() && is_win_digit(&bindings[i]))
+            .collect();
+        for &i in &shifted {
+            if let Some((_, mods)) = &mut combos[i] {
+                *mods |= MOD_SHIFT;
             }
         }
+        for j in 0..bindings.len() {
+            let Some((vk, mods)) = combos[j] else {
+                continue;
+            };
+            let Some(&i) = shifted
+                .iter()
+                .find(|&&i| i != j && combos[i] == Some((vk, mods)))
+            else {
+                continue;
+            };
+            let mut moved = bindings[j].clone();
+            moved.modifiers.push("ctrl".to_string());
+            log::info(&format!(
+                "win_number_needs_shift would turn {} into {}, which is already bound; \
+                 that binding moves to {}",
+                binding_label(&bindings[i]),
+                binding_label(&bindings[j]),
+                binding_label(&moved)
+            ));
+            combos[j] = Some((vk, mods | MOD_CONTROL));
+        }
+        combos
+    }
+
+    /// Register every configured binding. Returns the ids that could not be
+    /// registered (unparsable, or the combination is owned by another app).
+    unsafe fn register_hotkeys(app: &App, hwnd: HWND) -> Vec<i32> {
+        let Ok(mut st) = app.state.lock() else {
+            return Vec::new();
+        };
+        let st = &mut *st;
+        st.hotkeys.clear();
+
+        // Paused or behind a fullscreen window, only the pause toggle stays live.
+        let toggle_only = st.hotkeys_paused || st.suspended_for_fullscreen;
+        let mut failed = Vec::new();
+        let combos = hotkey_combos(&st.config);
+        for (i, (binding, combo)) in st.config.bindings.iter().zip(combos).enumerate() {
+            let id = binding_hotkey_id(i);
+            if toggle_only && binding.action != Action::TogglePause {
+                continue;
+            }
+            let Some((vk, mut mods)) = combo else {
+                failed.push(id);
+                continue;
+            };
+            if !binding.repeat {
+                mods |= MOD_NOREPEAT;
+            }
+            if unsafe { RegisterHotKey(hwnd, id, mods, vk) }.is_ok() {
+                st.hotkeys.insert(id, (binding.action, binding.desktop));
+            } else {
+                failed.push(id);
+            }
+        }
+        log::info(&format!(
+            "registered {} of {} hotkeys",
+            st.hotkeys.len(),
+            st.config.bindings.len()
+        ));
+        for &id in &failed {
+            if let Some(binding) = hotkey_binding(&st.config, id) {
+                log::error(&format!("hotkey {} not registered", binding_label(binding)));
+            }
+        }
+        failed
+    }
+
+    /// Live bindings whose desktop doesn't exist (yet), as "Alt+7 -> 7". They
+    /// stay registered, since adding desktops makes them work.
+    fn unreachable_bindings(app: &App) -> Vec<String> {
+        let Ok(count) = winvd::get_desktop_count() else {
+            return Vec::new();
+        };
+        let Ok(st) = app.state.lock() else {
+            return Vec::new();
+        };
+        st.config
+            .bindings
+            .iter()
+            .enumerate()
+            .filter(|(i, binding)| {
+                st.hotkeys.contains_key(&binding_hotkey_id(*i))
+                    && binding.action.targets_desktop()
+                    && binding.desktop >= count
+            })
+            .map(|(_, binding)| format!("{} -> {}", binding_label(binding), binding.desktop + 1))
+            .collect()
+    }
+
+    /// Log the bindings pointing past the last desktop, and show them unless
+    /// they are the built-in ones (which cover nine desktops whether or not
+    /// there are that many). Returns whether they were shown.
+    unsafe fn report_unreachable_bindings(app: &App, hwnd: HWND) -> bool {
+        let labels = unreachable_bindings(app);
+        if labels.is_empty() {
+            return false;
+        }
+        let list = labels.join(", ");
+        let defaults = app
+            .state
+            .lock()
+            .is_ok_and(|st| st.config.bindings == Config::default().bindings);
+        if defaults {
+            log::info(&format!("hotkeys for missing desktops: {}", list));
+            return false;
+        }
+        log::error(&format!("hotkeys for missing desktops: {}", list));
+        let text = strings::format(Label::MissingDesktops, list);
+        unsafe { show_balloon(app, hwnd, strings::text(Label::HotkeysTitle), &text) };
+        true
+    }
+
+    /// Tell the user which bindings are dead, via a tray balloon.
+    unsafe fn report_failed_hotkeys(app: &App, hwnd: HWND, failed: &[i32]) {
+        if failed.is_empty() {
+            return;
+        }
+        let labels: Vec<String> = app
+            .state
+            .lock()
+            .map(|st| {
+                failed
+                    .iter()
+                    .filter_map(|&id| hotkey_binding(&st.config, id))
+                    .map(binding_label)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let text = strings::format(Label::RegisterFailed, labels.join(", "));
+        unsafe { show_balloon(app, hwnd, strings::text(Label::HotkeysTitle), &text) };
+    }
+
+    /// Point out that live Win+digit hotkeys shadow the taskbar's app shortcuts.
+    unsafe fn warn_taskbar_shortcuts(app: &App, hwnd: HWND) {
+        let shadowed = app
+            .state
+            .lock()
+            .map(|st| {
+                !st.config.win_number_needs_shift
+                    && st.config.bindings.iter().enumerate().any(|(i, binding)| {
+                        st.hotkeys.contains_key(&binding_hotkey_id(i)) && is_win_digit(binding)
+                    })
+            })
+            .unwrap_or(false);
+        if shadowed {
+            let text = strings::text(Label::TaskbarShortcuts);
+            unsafe { show_balloon(app, hwnd, strings::text(Label::HotkeysTitle), text) };
+        }
     }
 
-    unsafe fn unregister_hotkeys(hwnd: HWND) {
-        for id in HOTKEY_FIRST..=HOTKEY_LAST {
+    unsafe fn unregister_hotkeys(app: &App, hwnd: HWND) {
+        let Ok(mut st) = app.state.lock() else {
+            return;
+        };
+        for (id, _) in st.hotkeys.drain() {
             unsafe {
                 let _ = UnregisterHotKey(hwnd, id);
             }
         }
     }
 
+    /// Re-register the hotkeys after a change to what should be live. The
+    /// tooltip may list them, so it is redone too.
+    unsafe fn refresh_hotkeys(app: &App, hwnd: HWND) {
+        unsafe {
+            unregister_hotkeys(app, hwnd);
+            let _ = register_hotkeys(app, hwnd);
+            update_tray_tip(app, hwnd);
+        }
+    }
+
+    /// Turn all hotkeys except the pause toggle off or back on.
+    unsafe fn set_hotkeys_paused(app: &App, hwnd: HWND, paused: bool) {
+        if let Ok(mut st) = app.state.lock() {
+            st.hotkeys_paused = paused;
+        }
+        log::info(if paused {
+            "hotkeys paused"
+        } else {
+            "hotkeys resumed"
+        });
+        unsafe { refresh_hotkeys(app, hwnd) };
+    }
+
+    fn hotkeys_paused(app: &App) -> bool {
+        app.state
+            .lock()
+            .map(|st| st.hotkeys_paused)
+            .unwrap_or(false)
+    }
+
+    /// Whether the foreground window covers its whole monitor, as borderless
+    /// fullscreen games do. The desktop itself is fullscreen-sized but doesn't count.
+    unsafe fn foreground_is_fullscreen() -> bool {
+        let fg = unsafe { GetForegroundWindow() };
+        if fg.0.is_null() || fg == unsafe { GetShellWindow() } {
+            return false;
+        }
+        if matches!(unsafe { window_class(fg) }.as_str(), "Progman" | "WorkerW") {
+            return false;
+        }
+        let mut rect = RECT::default();
+        if unsafe { GetWindowRect(fg, &mut rect) }.is_err() {
+            return false;
+        }
+        let monitor = unsafe { MonitorFromWindow(fg, MONITOR_DEFAULTTONEAREST) };
+        let mut info = MONITORINFO {
+            cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+            ..Default::default()
+        };
+        if !unsafe { GetMonitorInfoW(monitor, &mut info) }.as_bool() {
+            return false;
+        }
+        let screen = info.rcMonitor;
+        rect.left <= screen.left
+            && rect.top <= screen.top
+            && rect.right >= screen.right
+            && rect.bottom >= screen.bottom
+    }
+
+    /// Unregister the hotkeys while a fullscreen window is in front, so games
+    /// get Alt+number themselves, and bring them back afterwards.
+    unsafe fn update_fullscreen_suspend(app: &App, hwnd: HWND) {
+        let fullscreen = unsafe { foreground_is_fullscreen() };
+        let Ok(mut st) = app.state.lock() else {
+            return;
+        };
+        if st.suspended_for_fullscreen == fullscreen {
+            return;
+        }
+        st.suspended_for_fullscreen = fullscreen;
+        drop(st);
+        if fullscreen {
+            log::info("fullscreen window in front; hotkeys suspended");
+        } else {
+            log::info("fullscreen window gone; hotkeys restored");
+        }
+        unsafe { refresh_hotkeys(app, hwnd) };
+    }
+
+    /// Poll for fullscreen windows only while `suspend_in_fullscreen` is set.
+    /// Turning it off lifts a suspension in force at the next hotkey refresh.
+    unsafe fn update_fullscreen_timer(app: &App, hwnd: HWND) {
+        let Ok(mut st) = app.state.lock() else {
+            return;
+        };
+        if st.config.suspend_in_fullscreen {
+            drop(st);
+            unsafe { SetTimer(hwnd, TIMER_FULLSCREEN, FULLSCREEN_POLL_MS, None) };
+        } else {
+            st.suspended_for_fullscreen = false;
+            drop(st);
+            let _ = unsafe { KillTimer(hwnd, TIMER_FULLSCREEN) };
+        }
+    }
+
     unsafe extern "system" fn wndproc(
         hwnd: HWND,
         msg: u32,
         wparam: WPARAM,
         lparam: LPARAM,
     ) -> LRESULT {
+        if msg == WM_NCCREATE {
+            // `run` passes a reference to the App as the creation parameter.
+            let create = lparam.0 as *const CREATESTRUCTW;
+            unsafe { SetWindowLongPtrW(hwnd, GWLP_USERDATA, (*create).lpCreateParams as isize) };
+        } else if msg == WM_NCDESTROY {
+            // The last message; the window's reference goes with it.
+            let ptr = unsafe { SetWindowLongPtrW(hwnd, GWLP_USERDATA, 0) } as *const App;
+            if !ptr.is_null() {
+                drop(unsafe { Arc::from_raw(ptr) });
+            }
+            return unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) };
+        }
+        let Some(app) = (unsafe { window_app(hwnd) }) else {
+            return unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) };
+        };
+        let app = &app;
         match msg {
             WM_CREATE => {
-                let hicon =
-                    unsafe { LoadIconW(None, IDI_APPLICATION) }.unwrap_or(HICON(null_mut()));
+                HOOK_WINDOW.set(hwnd.0 as usize);
+                let current = winvd::get_current_desktop()
+                    .and_then(|d| d.get_index())
+                    .ok();
                 unsafe {
-                    add_tray_icon(hwnd, hicon);
-                    register_hotkeys(hwnd);
+                    let msg = RegisterWindowMessageW(PCWSTR(wstr("TaskbarCreated").as_ptr()));
+                    TASKBAR_CREATED.store(msg, Ordering::Relaxed);
+                    add_tray_icon(app, hwnd, current);
+                    let failed = register_hotkeys(app, hwnd);
+                    update_tray_tip(app, hwnd);
+                    // One balloon at a time; the most pressing one wins.
+                    if !failed.is_empty() {
+                        report_failed_hotkeys(app, hwnd, &failed);
+                    } else if !report_unreachable_bindings(app, hwnd) {
+                        warn_taskbar_shortcuts(app, hwnd);
+                    }
+                    SetTimer(hwnd, TIMER_PRUNE_FOCUS, PRUNE_FOCUS_INTERVAL_MS, None);
+                    update_fullscreen_timer(app, hwnd);
+                    if let Err(e) = WTSRegisterSessionNotification(hwnd, NOTIFY_FOR_THIS_SESSION) {
+                        log::error(&format!("WTSRegisterSessionNotification failed: {}", e));
+                    }
+                }
+                start_desktop_listener(app, hwnd);
+                LRESULT(0)
+            }
+            m if m != 0 && m == TASKBAR_CREATED.load(Ordering::Relaxed) => {
+                // Explorer restarted and dropped our icon.
+                let current = winvd::get_current_desktop()
+                    .and_then(|d| d.get_index())
+                    .ok();
+                unsafe { add_tray_icon(app, hwnd, current) };
+                LRESULT(0)
+            }
+            // Hotkeys can stop firing across a session lock, a logon or a
+            // remote reconnect; registering them again brings them back.
+            WM_WTSSESSION_CHANGE => {
+                if matches!(
+                    wparam.0 as u32,
+                    WTS_SESSION_UNLOCK | WTS_SESSION_LOGON | WTS_CONSOLE_CONNECT
+                ) {
+                    log::info(&format!(
+                        "session change {}; re-registering hotkeys",
+                        wparam.0
+                    ));
+                    unsafe { refresh_hotkeys(app, hwnd) };
                 }
                 LRESULT(0)
             }
             WM_HOTKEY => {
                 let id = wparam.0 as i32;
-                if (HOTKEY_FIRST..=HOTKEY_LAST).contains(&id) {
-                    let desktop_index = (id - 1) as u32;
-                    unsafe { try_switch_desktop(hwnd, desktop_index) };
+                let target = app.state.lock().ok().and_then(|mut st| {
+                    let target = st.hotkeys.get(&id).copied()?;
+                    // The same action on the same desktop again within the window is a
+                    // repeat (held or mashed keys); anything else goes through.
+                    let now = Instant::now();
+                    let window = Duration::from_millis(st.config.debounce_ms);
+                    if let Some((last, at)) = st.last_hotkey
+                        && last == target
+                        && now.duration_since(at) < window
+                    {
+                        return None;
+                    }
+                    st.last_hotkey = Some((target, now));
+                    Some(target)
+                });
+                match target {
+                    Some((Action::Switch, index)) => request_switch(app, index),
+                    Some((Action::Move, index)) if follow_on_move(app) => unsafe {
+                        move_window_and_follow(app, hwnd, index)
+                    },
+                    Some((Action::Move, index)) => {
+                        let _ = unsafe { move_foreground_window(app, hwnd, index) };
+                    }
+                    Some((Action::ShowDesktop, _)) => unsafe { toggle_show_desktop(app, hwnd) },
+                    Some((Action::PullWindow, _)) => unsafe { pull_recent_window(app, hwnd) },
+                    Some((Action::ToggleFollow, _)) => unsafe {
+                        set_follow_on_move(app, hwnd, !follow_on_move(app))
+                    },
+                    Some((Action::MoveFollow, index)) => unsafe {
+                        move_window_and_follow(app, hwnd, index)
+                    },
+                    Some((Action::Next, _)) => switch_relative(app, true),
+                    Some((Action::Previous, _)) => switch_relative(app, false),
+                    Some((Action::MoveNext, _)) => unsafe { move_window_relative(app, hwnd, true) },
+                    Some((Action::MovePrevious, _)) => unsafe {
+                        move_window_relative(app, hwnd, false)
+                    },
+                    Some((Action::Launch, _)) => {
+                        let binding = app
+                            .state
+                            .lock()
+                            .ok()
+                            .and_then(|st| hotkey_binding(&st.config, id).cloned());
+                        if let Some(binding) = binding {
+                            launch_on_desktop(app, &binding);
+                        }
+                    }
+                    Some((Action::SwapNext, _)) => unsafe { swap_with_adjacent(app, hwnd, true) },
+                    Some((Action::SwapPrevious, _)) => unsafe {
+                        swap_with_adjacent(app, hwnd, false)
+                    },
+                    Some((Action::First, _)) => request_switch(app, 0),
+                    Some((Action::Last, _)) => {
+                        if let Some(index) = end_desktop(true) {
+                            request_switch(app, index);
+                        }
+                    }
+                    Some((Action::MoveFirst, _)) => unsafe { move_window_to_end(app, hwnd, false) },
+                    Some((Action::MoveLast, _)) => unsafe { move_window_to_end(app, hwnd, true) },
+                    Some((Action::NewDesktop, _)) => unsafe { create_desktop(app, hwnd) },
+                    Some((Action::MoveToNewDesktop, _)) => unsafe {
+                        move_window_to_new_desktop(app, hwnd)
+                    },
+                    Some((Action::TogglePause, _)) => unsafe {
+                        set_hotkeys_paused(app, hwnd, !hotkeys_paused(app))
+                    },
+                    Some((Action::TogglePinWindow, _)) => unsafe {
+                        toggle_pin_foreground_window(app, hwnd)
+                    },
+                    Some((Action::TogglePinApp, _)) => unsafe {
+                        toggle_pin_foreground_app(app, hwnd)
+                    },
+                    Some((Action::GotoNumber, _)) => unsafe { start_goto_mode(app, hwnd) },
+                    Some((Action::PickDesktop, _)) => unsafe { pick_desktop(app, hwnd) },
+                    Some((Action::HistoryBack, _)) => navigate_history(app, false),
+                    Some((Action::HistoryForward, _)) => navigate_history(app, true),
+                    Some((Action::ToggleLast, _)) => {
+                        if let Some(index) = previous_desktop(app) {
+                            request_switch(app, index);
+                        }
+                    }
+                    None => {}
                 }
                 LRESULT(0)
             }
             WM_TRAYICON => {
-                // Right click (or key equivalent) on the tray icon.
-                if lparam.0 as u32 == WM_RBUTTONUP {
-                    unsafe { show_tray_menu(hwnd) };
+                // NOTIFYICON_VERSION_4: low word is the event, high word the icon id.
+                match (lparam.0 as u32) & 0xffff {
+                    // Right click, the menu key, or Enter with the icon focused.
+                    WM_CONTEXTMENU | NIN_KEYSELECT => unsafe { show_tray_menu(app, hwnd) },
+                    // The menu waits out the double-click time, so a double click
+                    // doesn't also pop it up.
+                    WM_LBUTTONUP => {
+                        let (enabled, after_double_click) = app
+                            .state
+                            .lock()
+                            .map(|mut st| {
+                                let after = std::mem::take(&mut st.swallow_tray_click);
+                                (st.config.left_click_menu, after)
+                            })
+                            .unwrap_or((true, false));
+                        if enabled && !after_double_click {
+                            unsafe { SetTimer(hwnd, TIMER_TRAY_CLICK, GetDoubleClickTime(), None) };
+                        }
+                    }
+                    WM_LBUTTONDBLCLK => {
+                        let target = app.state.lock().map(|mut st| {
+                            // The button-up that ends the double click is part of it.
+                            st.swallow_tray_click = true;
+                            st.config.double_click_desktop
+                        });
+                        let _ = unsafe { KillTimer(hwnd, TIMER_TRAY_CLICK) };
+                        if let Ok(index) = target {
+                            request_switch(app, index);
+                        }
+                    }
+                    WM_MBUTTONUP => {
+                        let action = app
+                            .state
+                            .lock()
+                            .map(|st| st.config.middle_click)
+                            .unwrap_or_default();
+                        match action {
+                            MiddleClick::None => {}
+                            MiddleClick::Next => switch_relative(app, true),
+                            MiddleClick::Previous => switch_relative(app, false),
+                            MiddleClick::ToggleLast => {
+                                if let Some(index) = previous_desktop(app) {
+                                    request_switch(app, index);
+                                }
+                            }
+                            MiddleClick::PickDesktop => unsafe { pick_desktop(app, hwnd) },
+                        }
+                    }
+                    // Hovering: make sure the wheel hook is listening.
+                    WM_MOUSEMOVE => unsafe { start_tray_wheel_hook(app, hwnd) },
+                    _ => {}
+                }
+                LRESULT(0)
+            }
+            WM_TIMER if wparam.0 == TIMER_GOTO => {
+                unsafe { end_goto_mode(app, true) };
+                LRESULT(0)
+            }
+            WM_TIMER if wparam.0 == TIMER_TRAY_CLICK => {
+                unsafe {
+                    let _ = KillTimer(hwnd, TIMER_TRAY_CLICK);
+                    show_tray_menu(app, hwnd);
+                }
+                LRESULT(0)
+            }
+            WM_TIMER if wparam.0 == TIMER_FULLSCREEN => {
+                unsafe { update_fullscreen_suspend(app, hwnd) };
+                LRESULT(0)
+            }
+            WM_TIMER if wparam.0 == TIMER_PRUNE_FOCUS => {
+                app.switcher.prune();
+                LRESULT(0)
+            }
+            WM_TIMER if wparam.0 == TIMER_TRAY_HOVER => {
+                let mut pt = windows::Win32::Foundation::POINT::default();
+                let over_icon = unsafe { GetCursorPos(&mut pt) }.is_ok()
+                    && unsafe { tray_icon_rect(hwnd) }
+                        .is_some_and(|rect| unsafe { PtInRect(&rect, pt) }.as_bool());
+                if !over_icon {
+                    unsafe { stop_tray_wheel_hook(app, hwnd) };
+                }
+                LRESULT(0)
+            }
+            WM_SWITCH_DONE => {
+                let index = wparam.0 as u32;
+                unsafe { update_tray_icon(app, hwnd, index) };
+                let (show, sound) = app
+                    .state
+                    .lock()
+                    .map(|st| {
+                        let sound = st.config.switch_sound.then(|| {
+                            st.config
+                                .switch_sound_file
+                                .clone()
+                                .unwrap_or_else(default_switch_sound)
+                        });
+                        (st.config.show_overlay, sound)
+                    })
+                    .unwrap_or((false, None));
+                // Quiet hours silence both; the switch itself has happened.
+                let (show, sound) = if in_quiet_hours(app) {
+                    (false, None)
+                } else {
+                    (show, sound)
+                };
+                if let Some(sound) = sound {
+                    unsafe { play_sound(&sound) };
+                }
+                if show {
+                    let name = winvd::get_desktop(index).get_name().unwrap_or_default();
+                    unsafe { overlay::show(&desktop_display_name(app, index, &name)) };
+                }
+                LRESULT(0)
+            }
+            WM_DESKTOP_CHANGED => {
+                let (new, old) = (wparam.0 as u32, lparam.0 as u32);
+                if new != old {
+                    app.switcher.set_previous_desktop(Some(old));
+                    unsafe { update_tray_icon(app, hwnd, new) };
                 }
                 LRESULT(0)
             }
+            WM_CONFIG_CHANGED => {
+                unsafe { reload_config(app, hwnd, false) };
+                LRESULT(0)
+            }
+            WM_REMOTE_RELOAD => {
+                unsafe { reload_config(app, hwnd, true) };
+                LRESULT(0)
+            }
+            WM_COPYDATA => {
+                let cds = unsafe { &*(lparam.0 as *const COPYDATASTRUCT) };
+                LRESULT(unsafe { handle_copydata(app, hwnd, cds) } as isize)
+            }
+            WM_REMOTE_GOTO => {
+                request_switch(app, wparam.0 as u32);
+                LRESULT(0)
+            }
             WM_COMMAND => {
-                let cmd = (wparam.0 & 0xffff) as usize;
+                let cmd = wparam.0 & 0xffff;
                 if cmd == MENU_EXIT_ID {
                     let _ = unsafe { DestroyWindow(hwnd) };
                     return LRESULT(0);
                 }
+                if cmd == MENU_ABOUT_ID {
+                    unsafe { show_about(hwnd) };
+                    return LRESULT(0);
+                }
+                if cmd == MENU_STARTUP_ID {
+                    set_run_at_startup(!run_at_startup_enabled());
+                    return LRESULT(0);
+                }
+                if cmd == MENU_HOTKEYS_ID {
+                    unsafe { set_hotkeys_paused(app, hwnd, !hotkeys_paused(app)) };
+                    return LRESULT(0);
+                }
+                if cmd == MENU_FOLLOW_ID {
+                    unsafe { set_follow_on_move(app, hwnd, !follow_on_move(app)) };
+                    return LRESULT(0);
+                }
+                if cmd == MENU_SETTINGS_ID {
+                    unsafe { open_settings(app, hwnd) };
+                    return LRESULT(0);
+                }
+                if cmd == MENU_RELOAD_ID {
+                    unsafe { reload_config(app, hwnd, true) };
+                    return LRESULT(0);
+                }
+                if cmd == MENU_NEW_DESKTOP_ID {
+                    unsafe { create_desktop(app, hwnd) };
+                    return LRESULT(0);
+                }
+                if cmd == MENU_ENSURE_DESKTOPS_ID {
+                    unsafe { ensure_desktops(app, hwnd) };
+                    return LRESULT(0);
+                }
+                if cmd == MENU_REMOVE_DESKTOP_ID {
+                    unsafe { remove_current_desktop(app, hwnd) };
+                    return LRESULT(0);
+                }
+                if cmd == MENU_MERGE_ID {
+                    unsafe { merge_all_to_first_desktop(app, hwnd) };
+                    return LRESULT(0);
+                }
+                if cmd == MENU_RENAME_DESKTOP_ID {
+                    unsafe { rename_current_desktop(app, hwnd) };
+                    return LRESULT(0);
+                }
 
-                // Desktop menu entries use IDs 1..9.
-                if (HOTKEY_FIRST as usize..=HOTKEY_LAST as usize).contains(&cmd) {
-                    unsafe { try_switch_desktop(hwnd, (cmd as u32) - 1) };
+                if let Some(index) = menu_id_to_desktop(cmd) {
+                    if shift_held() {
+                        // Shift+click sends the window that was in front there instead.
+                        let window = app.state.lock().map_or(0, |st| st.menu_window);
+                        if window != 0 {
+                            let _ =
+                                move_window_to(app, HWND(window as *mut core::ffi::c_void), index);
+                        }
+                    } else {
+                        request_switch(app, index);
+                    }
+                }
+                LRESULT(0)
+            }
+            // Never hold up a logoff or shutdown.
+            WM_QUERYENDSESSION => LRESULT(1),
+            // The process may be ended without a WM_DESTROY once this returns,
+            // so save and clean up now.
+            WM_ENDSESSION => {
+                if wparam.0 != 0 {
+                    log::info("session ending");
+                    unsafe {
+                        save_focus_map(app);
+                        unregister_hotkeys(app, hwnd);
+                        remove_tray_icon(hwnd);
+                    }
                 }
                 LRESULT(0)
             }
             WM_DESTROY => {
+                // Taken out first so the lock isn't held while the listener joins.
+                let listener = app
+                    .state
+                    .lock()
+                    .ok()
+                    .and_then(|mut st| st.desktop_events.take());
+                drop(listener);
                 unsafe {
-                    unregister_hotkeys(hwnd);
+                    let _ = KillTimer(hwnd, TIMER_PRUNE_FOCUS);
+                    let _ = KillTimer(hwnd, TIMER_FULLSCREEN);
+                    let _ = KillTimer(hwnd, TIMER_TRAY_CLICK);
+                    let _ = WTSUnRegisterSessionNotification(hwnd);
+                    save_focus_map(app);
+                    unregister_hotkeys(app, hwnd);
+                    stop_tray_wheel_hook(app, hwnd);
+                    end_goto_mode(app, false);
                     remove_tray_icon(hwnd);
+                    replace_tray_icon_handle(app, HICON(null_mut()));
                     PostQuitMessage(0);
                 }
                 LRESULT(0)
@@ -273,16 +3424,208 @@ mod app {
         }
     }
 
-    pub fn run() -> windows::core::Result<()> {
+    /// External command over WM_COPYDATA (see README). Returns true if handled.
+    unsafe fn handle_copydata(app: &Arc<App>, hwnd: HWND, cds: &COPYDATASTRUCT) -> bool {
+        if cds.cbData as usize != std::mem::size_of::<u32>() || cds.lpData.is_null() {
+            return false;
+        }
+        let mut bytes = [0u8; 4];
+        unsafe { std::ptr::copy_nonoverlapping(cds.lpData as *const u8, bytes.as_mut_ptr(), 4) };
+        let desktop_index = u32::from_le_bytes(bytes);
+
+        // Switches are range-checked by the worker, which may add desktops
+        // (`auto_create_desktops`); moves need the desktop to exist already.
+        if cds.dwData != COPYDATA_SWITCH
+            && !winvd::get_desktop_count().is_ok_and(|count| desktop_index < count)
+        {
+            return false;
+        }
+
+        match cds.dwData {
+            COPYDATA_SWITCH => request_switch(app, desktop_index),
+            COPYDATA_MOVE => {
+                let _ = unsafe { move_foreground_window(app, hwnd, desktop_index) };
+            }
+            COPYDATA_MOVE_FOLLOW => unsafe { move_window_and_follow(app, hwnd, desktop_index) },
+            _ => return false,
+        }
+        true
+    }
+
+    /// Panics would otherwise vanish with the process (a panic in `wndproc` aborts),
+    /// so record them in the log and tell the user before that happens.
+    fn install_panic_hook() {
+        std::panic::set_hook(Box::new(|info| {
+            let message = info
+                .payload()
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| info.payload().downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            let location = info
+                .location()
+                .map(|l| format!("{}:{}", l.file(), l.line()))
+                .unwrap_or_default();
+            let thread = std::thread::current();
+            let text = format!(
+                "panic in thread '{}' at {}: {}",
+                thread.name().unwrap_or("<unnamed>"),
+                location,
+                message
+            );
+            log::error(&text);
+
+            let text = wstr(&text);
+            let title = wstr(strings::text(Label::Crashed));
+            unsafe {
+                MessageBoxW(
+                    None,
+                    PCWSTR(text.as_ptr()),
+                    PCWSTR(title.as_ptr()),
+                    MB_OK | MB_ICONERROR,
+                )
+            };
+        }));
+    }
+
+    /// There is no console to print to, so bad arguments get a message box.
+    pub fn show_usage(error: &str) {
+        show_error(&format!("{}\n\n{}", error, cli::USAGE));
+    }
+
+    /// A message box for command-line failures, which have no console either.
+    pub fn show_error(text: &str) {
+        let text = wstr(text);
+        let title = wstr("d_switch");
         unsafe {
-            // Ensure COM is initialized for Win32 shell APIs / winvd internals.
-            let _ = windows::Win32::System::Com::CoInitializeEx(
+            MessageBoxW(
                 None,
-                windows::Win32::System::Com::COINIT_APARTMENTTHREADED,
-            );
+                PCWSTR(text.as_ptr()),
+                PCWSTR(title.as_ptr()),
+                MB_OK | MB_ICONERROR,
+            )
+        };
+    }
+
+    /// Ask the already-running instance to switch to `desktop_index`.
+    /// Returns false if no instance is running.
+    pub fn send_goto(desktop_index: u32) -> bool {
+        let class_name = wstr(WINDOW_CLASS);
+        let Ok(hwnd) = (unsafe { FindWindowW(PCWSTR(class_name.as_ptr()), PCWSTR(null())) }) else {
+            return false;
+        };
+        unsafe {
+            PostMessageW(
+                hwnd,
+                WM_REMOTE_GOTO,
+                WPARAM(desktop_index as usize),
+                LPARAM(0),
+            )
+        }
+        .is_ok()
+    }
+
+    /// Ask the already-running instance to re-read its config file.
+    /// Returns false if no instance is running.
+    pub fn send_reload() -> bool {
+        let class_name = wstr(WINDOW_CLASS);
+        let Ok(hwnd) = (unsafe { FindWindowW(PCWSTR(class_name.as_ptr()), PCWSTR(null())) }) else {
+            return false;
+        };
+        unsafe { PostMessageW(hwnd, WM_REMOTE_RELOAD, WPARAM(0), LPARAM(0)) }.is_ok()
+    }
+
+    /// Command-line `options` take precedence over the config file.
+    pub fn run(options: RunOptions) -> windows::core::Result<()> {
+        // A second instance would find every hotkey already taken; leave quietly.
+        // Hotkeys are per session, so each signed-in user gets their own.
+        let singleton = unsafe {
+            CreateMutexW(
+                None,
+                false,
+                PCWSTR(wstr("Local\\d_switch_singleton").as_ptr()),
+            )
+        }?;
+        if unsafe { GetLastError() } == ERROR_ALREADY_EXISTS {
+            let _ = unsafe { CloseHandle(singleton) };
+            return Ok(());
+        }
+
+        // Per-monitor aware, so the tray icon and overlay are drawn at full
+        // resolution. Fails harmlessly if a manifest already set it.
+        let _ =
+            unsafe { SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2) };
+
+        // Missing or unreadable config falls back to the built-in default bindings.
+        let config_path = options.config_path.or_else(Config::default_path);
+        let config = match config_path.as_deref().map(Config::load) {
+            Some(Ok(config)) => Ok(config),
+            Some(Err(e)) => Err(e),
+            None => Ok(Config::default()),
+        };
+        let level = options
+            .log_level
+            .unwrap_or_else(|| config.as_ref().map(|c| c.log_level).unwrap_or_default());
+        if let Some(path) = log::default_path() {
+            let _ = log::init(path, level);
+        }
+        log::info("starting");
+        if options.dry_run {
+            DRY_RUN.store(true, Ordering::Relaxed);
+            log::info("dry run: actions are logged, not carried out");
+        }
+        install_panic_hook();
+        let config = config.unwrap_or_else(|e| {
+            log::error(&format!("config not loaded, using defaults: {}", e));
+            Config::default()
+        });
+        strings::set_language(&config.language);
+        let retry_profile = pick_retry_profile();
+        log::info(&format!(
+            "switch retry profile: {} ({} x {} ms){}",
+            retry_profile.name,
+            retry_profile.retries,
+            retry_profile.delay_ms,
+            if config.switch_retries.is_some() || config.switch_retry_delay_ms.is_some() {
+                ", overridden by config"
+            } else {
+                ""
+            }
+        ));
+        let app = Arc::new_cyclic(|app: &Weak<App>| {
+            let mut switcher = DesktopSwitcher::new();
+            // Weak, as the switcher is part of the App they reach.
+            let fallback_app = app.clone();
+            switcher.set_focus_fallback(move |index| {
+                if let Some(app) = fallback_app.upgrade() {
+                    focus_desktop_default(&app, index);
+                }
+            });
+            let exclude_app = app.clone();
+            switcher.set_focus_exclude(move |hwnd| {
+                exclude_app
+                    .upgrade()
+                    .is_some_and(|app| excluded_from_focus(&app, hwnd))
+            });
+            App {
+                state: Mutex::new(State {
+                    config,
+                    config_path,
+                    retry_profile: Some(retry_profile),
+                    ..Default::default()
+                }),
+                switch_ready: Condvar::new(),
+                switcher,
+            }
+        });
+        unsafe { load_focus_map(&app) };
+
+        unsafe {
+            // For Win32 shell APIs and winvd; released after the message loop.
+            let com = ComApartment::init();
 
             let hinstance = GetModuleHandleW(PCWSTR(null()))?;
-            let class_name = wstr("d_switch_hidden_window");
+            let class_name = wstr(WINDOW_CLASS);
 
             let wc = WNDCLASSW {
                 style: CS_HREDRAW | CS_VREDRAW,
@@ -297,21 +3640,40 @@ mod app {
                 // If it fails, still try to proceed: CreateWindowExW will fail if needed.
             }
 
-            let _hwnd = CreateWindowExW(
-                Default::default(),
+            // Never shown, and a tool window so nothing lists it in Alt+Tab or the
+            // taskbar. Not message-only (HWND_MESSAGE): those miss the
+            // TaskbarCreated broadcast and FindWindowW can't find them for `goto`.
+            let hwnd = CreateWindowExW(
+                WS_EX_TOOLWINDOW,
                 PCWSTR(class_name.as_ptr()),
                 PCWSTR(wstr("d_switch").as_ptr()),
-                WS_OVERLAPPEDWINDOW,
-                CW_USEDEFAULT,
-                CW_USEDEFAULT,
-                CW_USEDEFAULT,
-                CW_USEDEFAULT,
+                WS_POPUP,
+                0,
+                0,
+                0,
+                0,
                 None,
                 None,
                 hinstance,
-                None,
+                Some(Arc::into_raw(Arc::clone(&app)) as *const core::ffi::c_void),
             )?;
 
+            app.switcher.ignore_window(hwnd);
+            spawn_switch_worker(Arc::clone(&app), hwnd);
+            spawn_rules_worker(Arc::clone(&app));
+            spawn_config_watcher(&app, hwnd);
+            let event_port = app.state.lock().ok().and_then(|st| st.config.event_port);
+            let socket_app = Arc::clone(&app);
+            if let Some(port) = event_port
+                && let Err(e) = event_socket::start(port, move |number| {
+                    if number >= 1 {
+                        request_switch(&socket_app, number - 1);
+                    }
+                })
+            {
+                log::error(&format!("event socket on port {}: {}", port, e));
+            }
+
             // Standard message loop.
             let mut msg = MSG::default();
             loop {
@@ -323,7 +3685,8 @@ mod app {
                 DispatchMessageW(&msg);
             }
 
-            let _ = windows::Win32::System::Com::CoUninitialize();
+            drop(com);
+            let _ = CloseHandle(singleton);
             Ok(())
         }
     }
@@ -331,5 +3694,25 @@ mod app {
 
 #[cfg(windows)]
 fn main() {
-    let _ = app::run();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match cli::parse(&args) {
+        // `d_switch goto 3`: hand desktop 3 to the running instance and exit.
+        Ok(cli::Command::Goto(number)) => {
+            if !app::send_goto(number - 1) {
+                app::show_error("d_switch is not running.");
+                std::process::exit(1);
+            }
+        }
+        // `d_switch reload`: have the running instance re-read its config.
+        Ok(cli::Command::Reload) => {
+            if !app::send_reload() {
+                app::show_error("d_switch is not running.");
+                std::process::exit(1);
+            }
+        }
+        Ok(cli::Command::Run(options)) => {
+            let _ = app::run(options);
+        }
+        Err(error) => app::show_usage(&error),
+    }
 }
@@ -0,0 +1,200 @@
+//! Center-screen "Desktop N" indicator shown briefly after a switch.
+
+use std::cell::Cell;
+use std::ptr::null;
+
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{COLORREF, HWND, LPARAM, LRESULT, RECT, WPARAM};
+use windows::Win32::Graphics::Gdi::{
+    BeginPaint, CreateFontW, CreateSolidBrush, DeleteObject, DrawTextW, EndPaint, FillRect,
+    SelectObject, SetBkMode, SetTextColor, CLEARTYPE_QUALITY, CLIP_DEFAULT_PRECIS, DEFAULT_CHARSET,
+    DEFAULT_PITCH, DT_CENTER, DT_SINGLELINE, DT_VCENTER, FF_DONTCARE, FW_SEMIBOLD,
+    OUT_DEFAULT_PRECIS, PAINTSTRUCT, TRANSPARENT,
+};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::UI::HiDpi::GetDpiForWindow;
+use windows::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DefWindowProcW, DestroyWindow, GetClientRect, GetSystemMetrics,
+    GetWindowTextW, KillTimer, RegisterClassW, SetLayeredWindowAttributes, SetTimer, SetWindowPos,
+    ShowWindow, LWA_ALPHA, SM_CXSCREEN, SM_CYSCREEN, SWP_NOACTIVATE, SWP_NOZORDER,
+    SW_SHOWNOACTIVATE, WM_DESTROY, WM_PAINT, WM_TIMER, WNDCLASSW, WS_EX_LAYERED, WS_EX_NOACTIVATE,
+    WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_EX_TRANSPARENT, WS_POPUP,
+};
+
+use crate::app::wstr;
+
+const CLASS_NAME: &str = "d_switch_overlay";
+const WIDTH: i32 = 360;
+const HEIGHT: i32 = 90;
+
+const TIMER_HOLD: usize = 1;
+const TIMER_FADE: usize = 2;
+const HOLD_MS: u32 = 700;
+const FADE_STEP_MS: u32 = 20;
+const FADE_STEP_ALPHA: u8 = 24;
+const START_ALPHA: u8 = 220;
+
+thread_local! {
+    // Only one overlay at a time; a new switch replaces the old one.
+    static CURRENT: Cell<isize> = const { Cell::new(0) };
+    static ALPHA: Cell<u8> = const { Cell::new(0) };
+}
+
+unsafe extern "system" fn overlay_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    match msg {
+        WM_PAINT => {
+            unsafe { paint(hwnd) };
+            LRESULT(0)
+        }
+        WM_TIMER => {
+            if wparam.0 == TIMER_HOLD {
+                unsafe {
+                    let _ = KillTimer(hwnd, TIMER_HOLD);
+                    SetTimer(hwnd, TIMER_FADE, FADE_STEP_MS, None);
+                }
+            } else if wparam.0 == TIMER_FADE {
+                let alpha = ALPHA.get().saturating_sub(FADE_STEP_ALPHA);
+                ALPHA.set(alpha);
+                if alpha == 0 {
+                    let _ = unsafe { DestroyWindow(hwnd) };
+                } else {
+                    let _ =
+                        unsafe { SetLayeredWindowAttributes(hwnd, COLORREF(0), alpha, LWA_ALPHA) };
+                }
+            }
+            LRESULT(0)
+        }
+        WM_DESTROY => {
+            unsafe {
+                let _ = KillTimer(hwnd, TIMER_HOLD);
+                let _ = KillTimer(hwnd, TIMER_FADE);
+            }
+            if CURRENT.get() == hwnd.0 as isize {
+                CURRENT.set(0);
+            }
+            LRESULT(0)
+        }
+        _ => unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) },
+    }
+}
+
+/// `value` in 96-DPI pixels, scaled to `dpi`.
+fn scale(value: i32, dpi: u32) -> i32 {
+    value * dpi as i32 / 96
+}
+
+/// The label is the window title, so nothing else needs to be stored.
+unsafe fn paint(hwnd: HWND) {
+    unsafe {
+        let mut ps = PAINTSTRUCT::default();
+        let dc = BeginPaint(hwnd, &mut ps);
+        let mut rect = RECT::default();
+        let _ = GetClientRect(hwnd, &mut rect);
+
+        let background = CreateSolidBrush(COLORREF(0x0020_2020));
+        FillRect(dc, &rect, background);
+        let _ = DeleteObject(background);
+
+        let face = wstr("Segoe UI");
+        let font = CreateFontW(
+            -scale(28, GetDpiForWindow(hwnd)),
+            0,
+            0,
+            0,
+            FW_SEMIBOLD.0 as i32,
+            0,
+            0,
+            0,
+            DEFAULT_CHARSET.0 as u32,
+            OUT_DEFAULT_PRECIS.0 as u32,
+            CLIP_DEFAULT_PRECIS.0 as u32,
+            CLEARTYPE_QUALITY.0 as u32,
+            (DEFAULT_PITCH.0 | FF_DONTCARE.0) as u32,
+            PCWSTR(face.as_ptr()),
+        );
+        let old_font = SelectObject(dc, font);
+        SetBkMode(dc, TRANSPARENT);
+        SetTextColor(dc, COLORREF(0x00FF_FFFF));
+
+        let mut buf = [0u16; 256];
+        let len = GetWindowTextW(hwnd, &mut buf).max(0) as usize;
+        DrawTextW(
+            dc,
+            &mut buf[..len],
+            &mut rect,
+            DT_CENTER | DT_VCENTER | DT_SINGLELINE,
+        );
+        SelectObject(dc, old_font);
+        let _ = DeleteObject(font);
+        let _ = EndPaint(hwnd, &ps);
+    }
+}
+
+/// Show `text` in the middle of the screen, then fade it out. Never takes focus.
+pub unsafe fn show(text: &str) {
+    let previous = CURRENT.replace(0);
+    if previous != 0 {
+        let _ = unsafe { DestroyWindow(HWND(previous as *mut core::ffi::c_void)) };
+    }
+
+    let Ok(hinstance) = (unsafe { GetModuleHandleW(PCWSTR(null())) }) else {
+        return;
+    };
+    let class_name = wstr(CLASS_NAME);
+    let wc = WNDCLASSW {
+        lpfnWndProc: Some(overlay_proc),
+        hInstance: hinstance.into(),
+        lpszClassName: PCWSTR(class_name.as_ptr()),
+        ..Default::default()
+    };
+    // Fails harmlessly once the class exists.
+    unsafe { RegisterClassW(&wc) };
+
+    let screen_w = unsafe { GetSystemMetrics(SM_CXSCREEN) };
+    let screen_h = unsafe { GetSystemMetrics(SM_CYSCREEN) };
+    let title = wstr(text);
+    // Tool window keeps it off the taskbar and Alt+Tab; transparent lets clicks through.
+    let Ok(hwnd) = (unsafe {
+        CreateWindowExW(
+            WS_EX_LAYERED | WS_EX_TOPMOST | WS_EX_NOACTIVATE | WS_EX_TOOLWINDOW | WS_EX_TRANSPARENT,
+            PCWSTR(class_name.as_ptr()),
+            PCWSTR(title.as_ptr()),
+            WS_POPUP,
+            0,
+            0,
+            WIDTH,
+            HEIGHT,
+            None,
+            None,
+            hinstance,
+            None,
+        )
+    }) else {
+        return;
+    };
+
+    CURRENT.set(hwnd.0 as isize);
+    ALPHA.set(START_ALPHA);
+    unsafe {
+        // Sized for the monitor it ends up on, then centered.
+        let dpi = GetDpiForWindow(hwnd);
+        let (w, h) = (scale(WIDTH, dpi), scale(HEIGHT, dpi));
+        let _ = SetWindowPos(
+            hwnd,
+            None,
+            (screen_w - w) / 2,
+            (screen_h - h) / 2,
+            w,
+            h,
+            SWP_NOZORDER | SWP_NOACTIVATE,
+        );
+        let _ = SetLayeredWindowAttributes(hwnd, COLORREF(0), START_ALPHA, LWA_ALPHA);
+        let _ = ShowWindow(hwnd, SW_SHOWNOACTIVATE);
+        SetTimer(hwnd, TIMER_HOLD, HOLD_MS, None);
+    }
+}
@@ -0,0 +1,337 @@
+//! Grid of all desktops, numbered and named, to pick one from with the
+//! keyboard or mouse.
+
+use std::cell::RefCell;
+use std::ptr::null;
+
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{COLORREF, HWND, LPARAM, LRESULT, RECT, WPARAM};
+use windows::Win32::Graphics::Gdi::{
+    BeginPaint, CreateFontW, CreateSolidBrush, DeleteObject, DrawTextW, EndPaint, FillRect,
+    InvalidateRect, SelectObject, SetBkMode, SetTextColor, CLEARTYPE_QUALITY, CLIP_DEFAULT_PRECIS,
+    DEFAULT_CHARSET, DEFAULT_PITCH, DT_CENTER, DT_END_ELLIPSIS, DT_SINGLELINE, DT_VCENTER,
+    FF_DONTCARE, FW_NORMAL, FW_SEMIBOLD, HFONT, OUT_DEFAULT_PRECIS, PAINTSTRUCT, TRANSPARENT,
+};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::UI::HiDpi::GetDpiForWindow;
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    SetFocus, VK_0, VK_DOWN, VK_ESCAPE, VK_LEFT, VK_NUMPAD0, VK_RETURN, VK_RIGHT, VK_UP,
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, GetMessageW,
+    GetSystemMetrics, PostQuitMessage, RegisterClassW, SetForegroundWindow, SetWindowPos,
+    ShowWindow, TranslateMessage, MSG, SM_CXSCREEN, SM_CYSCREEN, SWP_NOZORDER, SW_SHOW,
+    WA_INACTIVE, WM_ACTIVATE, WM_CLOSE, WM_KEYDOWN, WM_LBUTTONUP, WM_PAINT, WNDCLASSW,
+    WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_POPUP,
+};
+
+use crate::app::wstr;
+
+const CLASS_NAME: &str = "d_switch_picker";
+// Cell size and spacing in 96-DPI pixels.
+const CELL_W: i32 = 180;
+const CELL_H: i32 = 100;
+const GAP: i32 = 10;
+
+#[derive(Default)]
+struct Picker {
+    // Desktop display names by index.
+    names: Vec<String>,
+    selected: usize,
+    // Set while the grid is up; the choice is only filled on a pick.
+    active: bool,
+    choice: Option<u32>,
+}
+
+thread_local! {
+    // Runs a nested message loop on the UI thread, like the prompt; one at a time.
+    static PICKER: RefCell<Picker> = RefCell::new(Picker::default());
+}
+
+/// Columns for `count` cells: as square a grid as possible.
+fn columns(count: usize) -> usize {
+    (1..=count.max(1)).find(|c| c * c >= count).unwrap_or(1)
+}
+
+fn scale(value: i32, dpi: u32) -> i32 {
+    value * dpi as i32 / 96
+}
+
+/// Rect of cell `index`, in client coordinates.
+fn cell_rect(index: usize, cols: usize, dpi: u32) -> RECT {
+    let (col, row) = ((index % cols) as i32, (index / cols) as i32);
+    let left = scale(GAP + col * (CELL_W + GAP), dpi);
+    let top = scale(GAP + row * (CELL_H + GAP), dpi);
+    RECT {
+        left,
+        top,
+        right: left + scale(CELL_W, dpi),
+        bottom: top + scale(CELL_H, dpi),
+    }
+}
+
+unsafe fn finish(hwnd: HWND, choice: Option<u32>) {
+    PICKER.with(|p| {
+        let mut p = p.borrow_mut();
+        p.choice = choice;
+        p.active = false;
+    });
+    let _ = unsafe { DestroyWindow(hwnd) };
+}
+
+unsafe fn create_font(height: i32, weight: i32) -> HFONT {
+    let face = wstr("Segoe UI");
+    unsafe {
+        CreateFontW(
+            height,
+            0,
+            0,
+            0,
+            weight,
+            0,
+            0,
+            0,
+            DEFAULT_CHARSET.0 as u32,
+            OUT_DEFAULT_PRECIS.0 as u32,
+            CLIP_DEFAULT_PRECIS.0 as u32,
+            CLEARTYPE_QUALITY.0 as u32,
+            (DEFAULT_PITCH.0 | FF_DONTCARE.0) as u32,
+            PCWSTR(face.as_ptr()),
+        )
+    }
+}
+
+unsafe fn paint(hwnd: HWND) {
+    let (names, selected) = PICKER.with(|p| {
+        let p = p.borrow();
+        (p.names.clone(), p.selected)
+    });
+    let cols = columns(names.len());
+    unsafe {
+        let dpi = GetDpiForWindow(hwnd);
+        let mut ps = PAINTSTRUCT::default();
+        let dc = BeginPaint(hwnd, &mut ps);
+        let background = CreateSolidBrush(COLORREF(0x0020_2020));
+        FillRect(dc, &ps.rcPaint, background);
+        let _ = DeleteObject(background);
+
+        let number_font = create_font(-scale(34, dpi), FW_SEMIBOLD.0 as i32);
+        let name_font = create_font(-scale(15, dpi), FW_NORMAL.0 as i32);
+        SetBkMode(dc, TRANSPARENT);
+        SetTextColor(dc, COLORREF(0x00FF_FFFF));
+        let old_font = SelectObject(dc, number_font);
+        for (i, name) in names.iter().enumerate() {
+            let cell = cell_rect(i, cols, dpi);
+            // Selected cell in the accent blue (COLORREF is 0x00BBGGRR).
+            let color = if i == selected {
+                0x00D7_7800
+            } else {
+                0x0040_4040
+            };
+            let brush = CreateSolidBrush(COLORREF(color));
+            FillRect(dc, &cell, brush);
+            let _ = DeleteObject(brush);
+
+            let split = cell.top + (cell.bottom - cell.top) * 3 / 5;
+            let mut top = RECT {
+                bottom: split,
+                ..cell
+            };
+            let mut number: Vec<u16> = (i + 1).to_string().encode_utf16().collect();
+            SelectObject(dc, number_font);
+            DrawTextW(
+                dc,
+                &mut number,
+                &mut top,
+                DT_CENTER | DT_VCENTER | DT_SINGLELINE,
+            );
+            let mut bottom = RECT {
+                top: split,
+                left: cell.left + scale(6, dpi),
+                right: cell.right - scale(6, dpi),
+                ..cell
+            };
+            let mut label: Vec<u16> = name.encode_utf16().collect();
+            SelectObject(dc, name_font);
+            DrawTextW(
+                dc,
+                &mut label,
+                &mut bottom,
+                DT_CENTER | DT_SINGLELINE | DT_END_ELLIPSIS,
+            );
+        }
+        SelectObject(dc, old_font);
+        let _ = DeleteObject(number_font);
+        let _ = DeleteObject(name_font);
+        let _ = EndPaint(hwnd, &ps);
+    }
+}
+
+/// Move the selection by `dx` cells across and `dy` down, staying on the grid.
+unsafe fn move_selection(hwnd: HWND, dx: isize, dy: isize) {
+    PICKER.with(|p| {
+        let mut p = p.borrow_mut();
+        let count = p.names.len();
+        let cols = columns(count) as isize;
+        let target = p.selected as isize + dx + dy * cols;
+        if (0..count as isize).contains(&target) {
+            p.selected = target as usize;
+        }
+    });
+    let _ = unsafe { InvalidateRect(hwnd, None, false) };
+}
+
+unsafe extern "system" fn picker_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    match msg {
+        WM_PAINT => {
+            unsafe { paint(hwnd) };
+            LRESULT(0)
+        }
+        WM_KEYDOWN => {
+            let vk = wparam.0 as u16;
+            let count = PICKER.with(|p| p.borrow().names.len());
+            // 1..9 on the number row or the keypad pick that desktop directly.
+            let digit = if (VK_0.0 + 1..=VK_0.0 + 9).contains(&vk) {
+                Some(vk - VK_0.0)
+            } else if (VK_NUMPAD0.0 + 1..=VK_NUMPAD0.0 + 9).contains(&vk) {
+                Some(vk - VK_NUMPAD0.0)
+            } else {
+                None
+            };
+            unsafe {
+                match vk {
+                    _ if digit.is_some_and(|d| (d as usize) <= count) => {
+                        finish(hwnd, digit.map(|d| d as u32 - 1))
+                    }
+                    v if v == VK_ESCAPE.0 => finish(hwnd, None),
+                    v if v == VK_RETURN.0 => {
+                        let selected = PICKER.with(|p| p.borrow().selected);
+                        finish(hwnd, Some(selected as u32));
+                    }
+                    v if v == VK_LEFT.0 => move_selection(hwnd, -1, 0),
+                    v if v == VK_RIGHT.0 => move_selection(hwnd, 1, 0),
+                    v if v == VK_UP.0 => move_selection(hwnd, 0, -1),
+                    v if v == VK_DOWN.0 => move_selection(hwnd, 0, 1),
+                    _ => {}
+                }
+            }
+            LRESULT(0)
+        }
+        WM_LBUTTONUP => {
+            let x = (lparam.0 & 0xffff) as u16 as i16 as i32;
+            let y = ((lparam.0 >> 16) & 0xffff) as u16 as i16 as i32;
+            let dpi = unsafe { GetDpiForWindow(hwnd) };
+            let count = PICKER.with(|p| p.borrow().names.len());
+            let cols = columns(count);
+            let hit = (0..count).find(|&i| {
+                let r = cell_rect(i, cols, dpi);
+                x >= r.left && x < r.right && y >= r.top && y < r.bottom
+            });
+            if let Some(index) = hit {
+                unsafe { finish(hwnd, Some(index as u32)) };
+            }
+            LRESULT(0)
+        }
+        // Clicking anywhere else closes it, like Alt+Tab.
+        WM_ACTIVATE if (wparam.0 & 0xffff) as u32 == WA_INACTIVE => {
+            if PICKER.with(|p| p.borrow().active) {
+                unsafe { finish(hwnd, None) };
+            }
+            LRESULT(0)
+        }
+        WM_CLOSE => {
+            unsafe { finish(hwnd, None) };
+            LRESULT(0)
+        }
+        _ => unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) },
+    }
+}
+
+/// Whether the grid is up on this thread.
+pub fn is_active() -> bool {
+    PICKER.with(|p| p.borrow().active)
+}
+
+/// Show a grid of `names` (one per desktop, by index) with `current`
+/// selected, and return the index picked; `None` on Esc or when the grid
+/// loses focus. Blocks in a nested message loop until it closes. Returns
+/// `None` straight away if a grid is already up.
+pub unsafe fn pick_desktop(names: &[String], current: u32) -> Option<u32> {
+    if names.is_empty() || is_active() {
+        return None;
+    }
+
+    let hinstance = unsafe { GetModuleHandleW(PCWSTR(null())) }.ok()?;
+    let class_name = wstr(CLASS_NAME);
+    let wc = WNDCLASSW {
+        lpfnWndProc: Some(picker_proc),
+        hInstance: hinstance.into(),
+        lpszClassName: PCWSTR(class_name.as_ptr()),
+        ..Default::default()
+    };
+    // Fails harmlessly once the class exists.
+    unsafe { RegisterClassW(&wc) };
+
+    let hwnd = unsafe {
+        CreateWindowExW(
+            WS_EX_TOPMOST | WS_EX_TOOLWINDOW,
+            PCWSTR(class_name.as_ptr()),
+            PCWSTR(null()),
+            WS_POPUP,
+            0,
+            0,
+            0,
+            0,
+            None,
+            None,
+            hinstance,
+            None,
+        )
+    }
+    .ok()?;
+
+    PICKER.with(|p| {
+        *p.borrow_mut() = Picker {
+            names: names.to_vec(),
+            selected: (current as usize).min(names.len() - 1),
+            active: true,
+            choice: None,
+        }
+    });
+
+    unsafe {
+        // Sized for the grid at the monitor's scale, then centered.
+        let dpi = GetDpiForWindow(hwnd);
+        let cols = columns(names.len()) as i32;
+        let rows = (names.len() as i32 + cols - 1) / cols;
+        let w = scale(GAP + cols * (CELL_W + GAP), dpi);
+        let h = scale(GAP + rows * (CELL_H + GAP), dpi);
+        let x = (GetSystemMetrics(SM_CXSCREEN) - w) / 2;
+        let y = (GetSystemMetrics(SM_CYSCREEN) - h) / 2;
+        let _ = SetWindowPos(hwnd, None, x, y, w, h, SWP_NOZORDER);
+        let _ = ShowWindow(hwnd, SW_SHOW);
+        let _ = SetForegroundWindow(hwnd);
+        let _ = SetFocus(hwnd);
+
+        let mut msg = MSG::default();
+        while PICKER.with(|p| p.borrow().active) {
+            let res = GetMessageW(&mut msg, HWND::default(), 0, 0);
+            if res.0 <= 0 {
+                // WM_QUIT belongs to the outer loop.
+                PostQuitMessage(msg.wParam.0 as i32);
+                PICKER.with(|p| p.borrow_mut().active = false);
+                let _ = DestroyWindow(hwnd);
+                return None;
+            }
+            let _ = TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+    }
+
+    PICKER.with(|p| p.borrow_mut().choice.take())
+}
@@ -0,0 +1,356 @@
+//! Tiny modal text-input and confirmation windows built from plain Win32
+//! controls.
+
+use std::cell::RefCell;
+use std::ptr::null;
+
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use windows::Win32::Graphics::Gdi::{GetStockObject, COLOR_BTNFACE, DEFAULT_GUI_FONT, HBRUSH};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::UI::Controls::{BST_CHECKED, EM_SETSEL};
+use windows::Win32::UI::HiDpi::{SetThreadDpiAwarenessContext, DPI_AWARENESS_CONTEXT_UNAWARE};
+use windows::Win32::UI::Input::KeyboardAndMouse::SetFocus;
+use windows::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, GetDlgItem, GetMessageW,
+    GetSystemMetrics, GetWindowTextLengthW, GetWindowTextW, IsDialogMessageW, PostQuitMessage,
+    RegisterClassW, SendMessageW, SetForegroundWindow, TranslateMessage, BM_GETCHECK,
+    BS_AUTOCHECKBOX, BS_DEFPUSHBUTTON, BS_PUSHBUTTON, ES_AUTOHSCROLL, HMENU, IDCANCEL, IDOK, MSG,
+    SM_CXSCREEN, SM_CYSCREEN, WINDOW_STYLE, WM_CLOSE, WM_COMMAND, WM_SETFONT, WNDCLASSW, WS_BORDER,
+    WS_CAPTION, WS_CHILD, WS_EX_DLGMODALFRAME, WS_EX_TOPMOST, WS_POPUP, WS_SYSMENU, WS_TABSTOP,
+    WS_VISIBLE,
+};
+
+use crate::app::wstr;
+use crate::strings::{self, Label};
+
+const CLASS_NAME: &str = "d_switch_prompt";
+const CONFIRM_CLASS_NAME: &str = "d_switch_confirm";
+const EDIT_ID: i32 = 100;
+const DONT_ASK_ID: i32 = 101;
+
+#[derive(Default)]
+struct Prompt {
+    // Set once OK or Cancel is chosen; `text` is only filled on OK.
+    done: bool,
+    text: Option<String>,
+}
+
+#[derive(Default)]
+struct Confirm {
+    done: bool,
+    // Set on Yes, with whether "Don't ask again" was ticked.
+    answer: Option<bool>,
+}
+
+thread_local! {
+    // The prompt runs a nested message loop on the UI thread; one at a time.
+    static PROMPT: RefCell<Prompt> = RefCell::new(Prompt::default());
+    static CONFIRM: RefCell<Confirm> = RefCell::new(Confirm::default());
+}
+
+unsafe extern "system" fn prompt_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    match msg {
+        WM_COMMAND => {
+            let id = (wparam.0 & 0xffff) as i32;
+            if id == IDOK.0 {
+                let edit = unsafe { GetDlgItem(hwnd, EDIT_ID) }.unwrap_or_default();
+                let len = unsafe { GetWindowTextLengthW(edit) }.max(0) as usize;
+                let mut buf = vec![0u16; len + 1];
+                let read = unsafe { GetWindowTextW(edit, &mut buf) }.max(0) as usize;
+                let text = String::from_utf16_lossy(&buf[..read]);
+                PROMPT.with(|p| {
+                    let mut p = p.borrow_mut();
+                    p.text = Some(text);
+                    p.done = true;
+                });
+                let _ = unsafe { DestroyWindow(hwnd) };
+            } else if id == IDCANCEL.0 {
+                PROMPT.with(|p| p.borrow_mut().done = true);
+                let _ = unsafe { DestroyWindow(hwnd) };
+            }
+            LRESULT(0)
+        }
+        WM_CLOSE => {
+            PROMPT.with(|p| p.borrow_mut().done = true);
+            let _ = unsafe { DestroyWindow(hwnd) };
+            LRESULT(0)
+        }
+        _ => unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) },
+    }
+}
+
+unsafe extern "system" fn confirm_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    match msg {
+        WM_COMMAND => {
+            let id = (wparam.0 & 0xffff) as i32;
+            if id == IDOK.0 {
+                let check = unsafe { GetDlgItem(hwnd, DONT_ASK_ID) }.unwrap_or_default();
+                let state = unsafe { SendMessageW(check, BM_GETCHECK, WPARAM(0), LPARAM(0)) };
+                CONFIRM.with(|c| {
+                    let mut c = c.borrow_mut();
+                    c.answer = Some(state.0 == BST_CHECKED.0 as isize);
+                    c.done = true;
+                });
+                let _ = unsafe { DestroyWindow(hwnd) };
+            } else if id == IDCANCEL.0 {
+                CONFIRM.with(|c| c.borrow_mut().done = true);
+                let _ = unsafe { DestroyWindow(hwnd) };
+            }
+            LRESULT(0)
+        }
+        WM_CLOSE => {
+            CONFIRM.with(|c| c.borrow_mut().done = true);
+            let _ = unsafe { DestroyWindow(hwnd) };
+            LRESULT(0)
+        }
+        _ => unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) },
+    }
+}
+
+/// A visible child control with the stock GUI font.
+pub(crate) unsafe fn create_child(
+    parent: HWND,
+    class: &str,
+    text: &str,
+    style: WINDOW_STYLE,
+    (x, y, w, h): (i32, i32, i32, i32),
+    id: i32,
+) -> HWND {
+    let class = wstr(class);
+    let text = wstr(text);
+    let child = unsafe {
+        CreateWindowExW(
+            Default::default(),
+            PCWSTR(class.as_ptr()),
+            PCWSTR(text.as_ptr()),
+            WS_CHILD | WS_VISIBLE | style,
+            x,
+            y,
+            w,
+            h,
+            parent,
+            HMENU(id as isize as *mut core::ffi::c_void),
+            None,
+            None,
+        )
+    }
+    .unwrap_or_default();
+    unsafe {
+        let font = GetStockObject(DEFAULT_GUI_FONT);
+        SendMessageW(child, WM_SETFONT, WPARAM(font.0 as usize), LPARAM(1));
+    }
+    child
+}
+
+/// Ask for a line of text, pre-filled with `initial`. `None` on cancel.
+/// Blocks in a nested message loop until the window closes.
+pub unsafe fn prompt_text(title: &str, initial: &str) -> Option<String> {
+    const WIDTH: i32 = 320;
+    const HEIGHT: i32 = 120;
+
+    let hinstance = unsafe { GetModuleHandleW(PCWSTR(null())) }.ok()?;
+    let class_name = wstr(CLASS_NAME);
+    let wc = WNDCLASSW {
+        lpfnWndProc: Some(prompt_proc),
+        hInstance: hinstance.into(),
+        lpszClassName: PCWSTR(class_name.as_ptr()),
+        hbrBackground: HBRUSH((COLOR_BTNFACE.0 + 1) as isize as *mut core::ffi::c_void),
+        ..Default::default()
+    };
+    // Fails harmlessly once the class exists.
+    unsafe { RegisterClassW(&wc) };
+
+    // Laid out in 96-DPI pixels; created DPI-unaware so Windows scales it up.
+    let dpi_context = unsafe { SetThreadDpiAwarenessContext(DPI_AWARENESS_CONTEXT_UNAWARE) };
+    let x = (unsafe { GetSystemMetrics(SM_CXSCREEN) } - WIDTH) / 2;
+    let y = (unsafe { GetSystemMetrics(SM_CYSCREEN) } - HEIGHT) / 2;
+    let title = wstr(title);
+    let dlg = unsafe {
+        CreateWindowExW(
+            WS_EX_DLGMODALFRAME | WS_EX_TOPMOST,
+            PCWSTR(class_name.as_ptr()),
+            PCWSTR(title.as_ptr()),
+            WS_POPUP | WS_CAPTION | WS_SYSMENU | WS_VISIBLE,
+            x,
+            y,
+            WIDTH,
+            HEIGHT,
+            None,
+            None,
+            hinstance,
+            None,
+        )
+    };
+    let Ok(dlg) = dlg else {
+        unsafe { SetThreadDpiAwarenessContext(dpi_context) };
+        return None;
+    };
+
+    PROMPT.with(|p| *p.borrow_mut() = Prompt::default());
+
+    unsafe {
+        let edit_style = WS_BORDER | WS_TABSTOP | WINDOW_STYLE(ES_AUTOHSCROLL as u32);
+        let edit = create_child(dlg, "EDIT", initial, edit_style, (10, 10, 290, 24), EDIT_ID);
+        let ok_style = WS_TABSTOP | WINDOW_STYLE(BS_DEFPUSHBUTTON as u32);
+        create_child(
+            dlg,
+            "BUTTON",
+            strings::text(Label::Ok),
+            ok_style,
+            (130, 45, 80, 26),
+            IDOK.0,
+        );
+        let cancel_style = WS_TABSTOP | WINDOW_STYLE(BS_PUSHBUTTON as u32);
+        create_child(
+            dlg,
+            "BUTTON",
+            strings::text(Label::Cancel),
+            cancel_style,
+            (220, 45, 80, 26),
+            IDCANCEL.0,
+        );
+
+        SetThreadDpiAwarenessContext(dpi_context);
+        let _ = SetForegroundWindow(dlg);
+        let _ = SetFocus(edit);
+        SendMessageW(edit, EM_SETSEL, WPARAM(0), LPARAM(-1));
+
+        // IsDialogMessageW gives us Tab, Enter (= OK) and Esc (= Cancel).
+        let mut msg = MSG::default();
+        while !PROMPT.with(|p| p.borrow().done) {
+            let res = GetMessageW(&mut msg, HWND::default(), 0, 0);
+            if res.0 <= 0 {
+                // WM_QUIT belongs to the outer loop.
+                PostQuitMessage(msg.wParam.0 as i32);
+                let _ = DestroyWindow(dlg);
+                return None;
+            }
+            if IsDialogMessageW(dlg, &msg).as_bool() {
+                continue;
+            }
+            let _ = TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+    }
+
+    PROMPT.with(|p| p.borrow_mut().text.take())
+}
+
+/// Ask a yes/no question with a "Don't ask again" checkbox. `None` means No;
+/// `Some(dont_ask)` means Yes, and whether the box was ticked. Blocks in a
+/// nested message loop until the window closes.
+pub unsafe fn confirm(text: &str) -> Option<bool> {
+    const WIDTH: i32 = 360;
+    const HEIGHT: i32 = 150;
+
+    let hinstance = unsafe { GetModuleHandleW(PCWSTR(null())) }.ok()?;
+    let class_name = wstr(CONFIRM_CLASS_NAME);
+    let wc = WNDCLASSW {
+        lpfnWndProc: Some(confirm_proc),
+        hInstance: hinstance.into(),
+        lpszClassName: PCWSTR(class_name.as_ptr()),
+        hbrBackground: HBRUSH((COLOR_BTNFACE.0 + 1) as isize as *mut core::ffi::c_void),
+        ..Default::default()
+    };
+    // Fails harmlessly once the class exists.
+    unsafe { RegisterClassW(&wc) };
+
+    // Laid out like the prompt, in 96-DPI pixels.
+    let dpi_context = unsafe { SetThreadDpiAwarenessContext(DPI_AWARENESS_CONTEXT_UNAWARE) };
+    let x = (unsafe { GetSystemMetrics(SM_CXSCREEN) } - WIDTH) / 2;
+    let y = (unsafe { GetSystemMetrics(SM_CYSCREEN) } - HEIGHT) / 2;
+    let title = wstr("d_switch");
+    let dlg = unsafe {
+        CreateWindowExW(
+            WS_EX_DLGMODALFRAME | WS_EX_TOPMOST,
+            PCWSTR(class_name.as_ptr()),
+            PCWSTR(title.as_ptr()),
+            WS_POPUP | WS_CAPTION | WS_SYSMENU | WS_VISIBLE,
+            x,
+            y,
+            WIDTH,
+            HEIGHT,
+            None,
+            None,
+            hinstance,
+            None,
+        )
+    };
+    let Ok(dlg) = dlg else {
+        unsafe { SetThreadDpiAwarenessContext(dpi_context) };
+        return None;
+    };
+
+    CONFIRM.with(|c| *c.borrow_mut() = Confirm::default());
+
+    unsafe {
+        create_child(
+            dlg,
+            "STATIC",
+            text,
+            Default::default(),
+            (10, 10, 330, 36),
+            -1,
+        );
+        let check_style = WS_TABSTOP | WINDOW_STYLE(BS_AUTOCHECKBOX as u32);
+        create_child(
+            dlg,
+            "BUTTON",
+            strings::text(Label::DontAskAgain),
+            check_style,
+            (10, 50, 330, 22),
+            DONT_ASK_ID,
+        );
+        let yes_style = WS_TABSTOP | WINDOW_STYLE(BS_DEFPUSHBUTTON as u32);
+        let yes = create_child(
+            dlg,
+            "BUTTON",
+            strings::text(Label::Yes),
+            yes_style,
+            (170, 80, 80, 26),
+            IDOK.0,
+        );
+        let no_style = WS_TABSTOP | WINDOW_STYLE(BS_PUSHBUTTON as u32);
+        create_child(
+            dlg,
+            "BUTTON",
+            strings::text(Label::No),
+            no_style,
+            (260, 80, 80, 26),
+            IDCANCEL.0,
+        );
+
+        SetThreadDpiAwarenessContext(dpi_context);
+        let _ = SetForegroundWindow(dlg);
+        let _ = SetFocus(yes);
+
+        let mut msg = MSG::default();
+        while !CONFIRM.with(|c| c.borrow().done) {
+            let res = GetMessageW(&mut msg, HWND::default(), 0, 0);
+            if res.0 <= 0 {
+                // WM_QUIT belongs to the outer loop.
+                PostQuitMessage(msg.wParam.0 as i32);
+                let _ = DestroyWindow(dlg);
+                return None;
+            }
+            if IsDialogMessageW(dlg, &msg).as_bool() {
+                continue;
+            }
+            let _ = TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+    }
+
+    CONFIRM.with(|c| c.borrow_mut().answer.take())
+}
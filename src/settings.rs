@@ -0,0 +1,476 @@
+//! Settings window for the hotkey bindings and a few switches, built from
+//! plain Win32 controls like the rename prompt.
+
+use std::cell::RefCell;
+use std::ptr::null;
+
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use windows::Win32::Graphics::Gdi::{COLOR_BTNFACE, HBRUSH};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::UI::Controls::BST_CHECKED;
+use windows::Win32::UI::HiDpi::{SetThreadDpiAwarenessContext, DPI_AWARENESS_CONTEXT_UNAWARE};
+use windows::Win32::UI::Input::KeyboardAndMouse::{EnableWindow, SetFocus};
+use windows::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, GetDlgItem, GetMessageW,
+    GetSystemMetrics, GetWindowTextLengthW, GetWindowTextW, IsDialogMessageW, MessageBoxW,
+    PostQuitMessage, RegisterClassW, SendMessageW, SetForegroundWindow, SetWindowTextW,
+    TranslateMessage, BM_GETCHECK, BM_SETCHECK, BS_AUTOCHECKBOX, BS_DEFPUSHBUTTON, BS_PUSHBUTTON,
+    ES_AUTOHSCROLL, ES_NUMBER, IDCANCEL, IDOK, LBN_SELCHANGE, LBS_NOTIFY, LB_ADDSTRING,
+    LB_GETCURSEL, LB_RESETCONTENT, LB_SETCURSEL, MB_ICONWARNING, MB_OK, MSG, SM_CXSCREEN,
+    SM_CYSCREEN, WINDOW_STYLE, WM_CLOSE, WM_COMMAND, WNDCLASSW, WS_BORDER, WS_CAPTION,
+    WS_EX_DLGMODALFRAME, WS_EX_TOPMOST, WS_POPUP, WS_SYSMENU, WS_TABSTOP, WS_VISIBLE, WS_VSCROLL,
+};
+
+use crate::app::{binding_label, parse_key, wstr};
+use crate::config::{Action, Config};
+use crate::prompt::create_child;
+use crate::strings::{self, Label};
+
+const CLASS_NAME: &str = "d_switch_settings";
+
+const LIST_ID: i32 = 200;
+const KEY_ID: i32 = 201;
+const DESKTOP_ID: i32 = 202;
+const UPDATE_ID: i32 = 203;
+const WRAP_ID: i32 = 204;
+const SKIP_EMPTY_ID: i32 = 205;
+const CONFIRM_ID: i32 = 206;
+// One checkbox per modifier, in this order.
+const MODIFIER_FIRST_ID: i32 = 210;
+const MODIFIERS: [(&str, &str); 4] = [
+    ("ctrl", "Ctrl"),
+    ("alt", "Alt"),
+    ("shift", "Shift"),
+    ("win", "Win"),
+];
+
+#[derive(Default)]
+struct Settings {
+    // The copy being edited; only handed back on Save.
+    config: Config,
+    // The binding shown in the edit controls. On a selection change the list
+    // has already moved on, so this is the one the edits belong to.
+    shown: Option<usize>,
+    done: bool,
+    saved: bool,
+}
+
+thread_local! {
+    // Runs a nested message loop on the UI thread, like the prompt; one at a time.
+    static SETTINGS: RefCell<Settings> = RefCell::new(Settings::default());
+}
+
+fn action_label(action: Action, desktop: u32) -> String {
+    let label = match action {
+        Action::Switch => Label::ActionSwitch,
+        Action::Move => Label::ActionMove,
+        Action::MoveFollow => Label::ActionMoveFollow,
+        Action::Next => Label::ActionNext,
+        Action::Previous => Label::ActionPrevious,
+        Action::MoveNext => Label::ActionMoveNext,
+        Action::First => Label::ActionFirst,
+        Action::Last => Label::ActionLast,
+        Action::MoveFirst => Label::ActionMoveFirst,
+        Action::MoveLast => Label::ActionMoveLast,
+        Action::MovePrevious => Label::ActionMovePrevious,
+        Action::ToggleLast => Label::ActionToggleLast,
+        Action::HistoryBack => Label::ActionHistoryBack,
+        Action::HistoryForward => Label::ActionHistoryForward,
+        Action::NewDesktop => Label::NewDesktop,
+        Action::TogglePause => Label::ActionTogglePause,
+        Action::TogglePinWindow => Label::ActionTogglePinWindow,
+        Action::TogglePinApp => Label::ActionTogglePinApp,
+        Action::MoveToNewDesktop => Label::ActionMoveToNewDesktop,
+        Action::GotoNumber => Label::ActionGotoNumber,
+        Action::PickDesktop => Label::ActionPickDesktop,
+        Action::ToggleFollow => Label::ActionToggleFollow,
+        Action::ShowDesktop => Label::ActionShowDesktop,
+        Action::PullWindow => Label::ActionPullWindow,
+        Action::SwapNext => Label::ActionSwapNext,
+        Action::SwapPrevious => Label::ActionSwapPrevious,
+        Action::Launch => Label::ActionLaunch,
+    };
+    strings::format(label, desktop + 1)
+}
+
+unsafe fn item(dlg: HWND, id: i32) -> HWND {
+    unsafe { GetDlgItem(dlg, id) }.unwrap_or_default()
+}
+
+unsafe fn window_text(hwnd: HWND) -> String {
+    let len = unsafe { GetWindowTextLengthW(hwnd) }.max(0) as usize;
+    let mut buf = vec![0u16; len + 1];
+    let read = unsafe { GetWindowTextW(hwnd, &mut buf) }.max(0) as usize;
+    String::from_utf16_lossy(&buf[..read])
+}
+
+unsafe fn is_checked(dlg: HWND, id: i32) -> bool {
+    let state = unsafe { SendMessageW(item(dlg, id), BM_GETCHECK, WPARAM(0), LPARAM(0)) };
+    state.0 == BST_CHECKED.0 as isize
+}
+
+unsafe fn set_checked(dlg: HWND, id: i32, checked: bool) {
+    unsafe {
+        SendMessageW(
+            item(dlg, id),
+            BM_SETCHECK,
+            WPARAM(checked as usize),
+            LPARAM(0),
+        )
+    };
+}
+
+/// The list box selection, if any.
+unsafe fn selected(dlg: HWND) -> Option<usize> {
+    let sel = unsafe { SendMessageW(item(dlg, LIST_ID), LB_GETCURSEL, WPARAM(0), LPARAM(0)) };
+    usize::try_from(sel.0).ok()
+}
+
+/// Rebuild the list from the edited config, keeping `select` selected.
+unsafe fn fill_list(dlg: HWND, select: Option<usize>) {
+    let list = unsafe { item(dlg, LIST_ID) };
+    unsafe { SendMessageW(list, LB_RESETCONTENT, WPARAM(0), LPARAM(0)) };
+    SETTINGS.with(|s| {
+        for binding in &s.borrow().config.bindings {
+            let text = format!(
+                "{}  \u{2013}  {}",
+                binding_label(binding),
+                action_label(binding.action, binding.desktop)
+            );
+            let text = wstr(&text);
+            unsafe {
+                SendMessageW(
+                    list,
+                    LB_ADDSTRING,
+                    WPARAM(0),
+                    LPARAM(text.as_ptr() as isize),
+                )
+            };
+        }
+    });
+    if let Some(index) = select {
+        unsafe { SendMessageW(list, LB_SETCURSEL, WPARAM(index), LPARAM(0)) };
+    }
+}
+
+/// Show the selected binding in the edit controls.
+unsafe fn load_selected(dlg: HWND) {
+    let index = unsafe { selected(dlg) };
+    let Some(binding) = SETTINGS.with(|s| {
+        let mut s = s.borrow_mut();
+        s.shown = index;
+        index.and_then(|i| s.config.bindings.get(i).cloned())
+    }) else {
+        return;
+    };
+    unsafe {
+        for (i, (name, _)) in MODIFIERS.iter().enumerate() {
+            let on = binding
+                .modifiers
+                .iter()
+                .any(|m| m.trim().eq_ignore_ascii_case(name));
+            set_checked(dlg, MODIFIER_FIRST_ID + i as i32, on);
+        }
+        let key = wstr(binding.key.trim());
+        let _ = SetWindowTextW(item(dlg, KEY_ID), PCWSTR(key.as_ptr()));
+        let uses_desktop = binding.action.targets_desktop();
+        let desktop = if uses_desktop {
+            (binding.desktop + 1).to_string()
+        } else {
+            String::new()
+        };
+        let desktop = wstr(&desktop);
+        let _ = SetWindowTextW(item(dlg, DESKTOP_ID), PCWSTR(desktop.as_ptr()));
+        let _ = EnableWindow(item(dlg, DESKTOP_ID), uses_desktop);
+    }
+}
+
+/// Copy the edit controls into the binding they show. Nothing shown is fine;
+/// an unknown key or desktop number is reported and leaves it unchanged.
+unsafe fn apply_selected(dlg: HWND) -> bool {
+    let Some(index) = SETTINGS.with(|s| s.borrow().shown) else {
+        return true;
+    };
+    let key = unsafe { window_text(item(dlg, KEY_ID)) }
+        .trim()
+        .to_ascii_lowercase();
+    if parse_key(&key).is_none() {
+        unsafe { warn(dlg, &strings::format(Label::UnknownKey, &key)) };
+        return false;
+    }
+    let desktop_text = unsafe { window_text(item(dlg, DESKTOP_ID)) };
+    let modifiers: Vec<String> = MODIFIERS
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| unsafe { is_checked(dlg, MODIFIER_FIRST_ID + i as i32) })
+        .map(|(_, (name, _))| name.to_string())
+        .collect();
+
+    let ok = SETTINGS.with(|s| {
+        let mut s = s.borrow_mut();
+        let Some(binding) = s.config.bindings.get_mut(index) else {
+            return true;
+        };
+        if binding.action.targets_desktop() {
+            match desktop_text.trim().parse::<u32>() {
+                Ok(n @ 1..) => binding.desktop = n - 1,
+                _ => return false,
+            }
+        }
+        binding.key = key;
+        binding.modifiers = modifiers;
+        true
+    });
+    if !ok {
+        unsafe { warn(dlg, strings::text(Label::DesktopNumberNeeded)) };
+        return false;
+    }
+    let select = unsafe { selected(dlg) };
+    unsafe { fill_list(dlg, select) };
+    true
+}
+
+unsafe fn warn(dlg: HWND, text: &str) {
+    let text = wstr(text);
+    let title = wstr(strings::text(Label::SettingsTitle));
+    unsafe {
+        MessageBoxW(
+            dlg,
+            PCWSTR(text.as_ptr()),
+            PCWSTR(title.as_ptr()),
+            MB_OK | MB_ICONWARNING,
+        )
+    };
+}
+
+unsafe fn close(dlg: HWND, saved: bool) {
+    SETTINGS.with(|s| {
+        let mut s = s.borrow_mut();
+        s.saved = saved;
+        s.done = true;
+    });
+    let _ = unsafe { DestroyWindow(dlg) };
+}
+
+unsafe extern "system" fn settings_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    match msg {
+        WM_COMMAND => {
+            let id = (wparam.0 & 0xffff) as i32;
+            let code = ((wparam.0 >> 16) & 0xffff) as u32;
+            if id == LIST_ID && code == LBN_SELCHANGE {
+                // Keep edits to the binding being left, or stay on it if
+                // they don't make sense.
+                if unsafe { apply_selected(hwnd) } {
+                    unsafe { load_selected(hwnd) };
+                } else if let Some(shown) = SETTINGS.with(|s| s.borrow().shown) {
+                    let list = unsafe { item(hwnd, LIST_ID) };
+                    unsafe { SendMessageW(list, LB_SETCURSEL, WPARAM(shown), LPARAM(0)) };
+                }
+            } else if id == UPDATE_ID {
+                unsafe { apply_selected(hwnd) };
+            } else if id == IDOK.0 {
+                // Unapplied edits to the selected binding are saved too.
+                if unsafe { apply_selected(hwnd) } {
+                    let wrap = unsafe { is_checked(hwnd, WRAP_ID) };
+                    let skip_empty = unsafe { is_checked(hwnd, SKIP_EMPTY_ID) };
+                    let confirm = unsafe { is_checked(hwnd, CONFIRM_ID) };
+                    SETTINGS.with(|s| {
+                        let config = &mut s.borrow_mut().config;
+                        config.wrap_around = wrap;
+                        config.skip_empty = skip_empty;
+                        config.confirm_destructive = confirm;
+                    });
+                    unsafe { close(hwnd, true) };
+                }
+            } else if id == IDCANCEL.0 {
+                unsafe { close(hwnd, false) };
+            }
+            LRESULT(0)
+        }
+        WM_CLOSE => {
+            unsafe { close(hwnd, false) };
+            LRESULT(0)
+        }
+        _ => unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) },
+    }
+}
+
+/// Let the user edit `config`'s bindings, wrap-around, skip-empty and whether
+/// to confirm removing or merging desktops. Returns the edited copy on Save,
+/// `None` on cancel. Blocks in a nested message loop until the window closes.
+pub unsafe fn edit_settings(config: &Config) -> Option<Config> {
+    const WIDTH: i32 = 430;
+    const HEIGHT: i32 = 455;
+
+    let hinstance = unsafe { GetModuleHandleW(PCWSTR(null())) }.ok()?;
+    let class_name = wstr(CLASS_NAME);
+    let wc = WNDCLASSW {
+        lpfnWndProc: Some(settings_proc),
+        hInstance: hinstance.into(),
+        lpszClassName: PCWSTR(class_name.as_ptr()),
+        hbrBackground: HBRUSH((COLOR_BTNFACE.0 + 1) as isize as *mut core::ffi::c_void),
+        ..Default::default()
+    };
+    // Fails harmlessly once the class exists.
+    unsafe { RegisterClassW(&wc) };
+
+    // Laid out in 96-DPI pixels; created DPI-unaware so Windows scales it up.
+    let dpi_context = unsafe { SetThreadDpiAwarenessContext(DPI_AWARENESS_CONTEXT_UNAWARE) };
+    let x = (unsafe { GetSystemMetrics(SM_CXSCREEN) } - WIDTH) / 2;
+    let y = (unsafe { GetSystemMetrics(SM_CYSCREEN) } - HEIGHT) / 2;
+    let title = wstr(strings::text(Label::SettingsTitle));
+    let dlg = unsafe {
+        CreateWindowExW(
+            WS_EX_DLGMODALFRAME | WS_EX_TOPMOST,
+            PCWSTR(class_name.as_ptr()),
+            PCWSTR(title.as_ptr()),
+            WS_POPUP | WS_CAPTION | WS_SYSMENU | WS_VISIBLE,
+            x,
+            y,
+            WIDTH,
+            HEIGHT,
+            None,
+            None,
+            hinstance,
+            None,
+        )
+    };
+    let Ok(dlg) = dlg else {
+        unsafe { SetThreadDpiAwarenessContext(dpi_context) };
+        return None;
+    };
+
+    SETTINGS.with(|s| {
+        *s.borrow_mut() = Settings {
+            config: config.clone(),
+            ..Default::default()
+        }
+    });
+
+    unsafe {
+        let list_style = WS_BORDER | WS_TABSTOP | WS_VSCROLL | WINDOW_STYLE(LBS_NOTIFY as u32);
+        let list = create_child(dlg, "LISTBOX", "", list_style, (10, 10, 400, 210), LIST_ID);
+
+        let check_style = WS_TABSTOP | WINDOW_STYLE(BS_AUTOCHECKBOX as u32);
+        for (i, (_, label)) in MODIFIERS.iter().enumerate() {
+            let x = 10 + 65 * i as i32;
+            let id = MODIFIER_FIRST_ID + i as i32;
+            create_child(dlg, "BUTTON", label, check_style, (x, 228, 60, 22), id);
+        }
+        let edit_style = WS_BORDER | WS_TABSTOP | WINDOW_STYLE(ES_AUTOHSCROLL as u32);
+        create_child(
+            dlg,
+            "STATIC",
+            strings::text(Label::KeyField),
+            Default::default(),
+            (10, 263, 40, 20),
+            -1,
+        );
+        create_child(dlg, "EDIT", "", edit_style, (50, 259, 80, 24), KEY_ID);
+        create_child(
+            dlg,
+            "STATIC",
+            strings::text(Label::DesktopField),
+            Default::default(),
+            (145, 263, 55, 20),
+            -1,
+        );
+        let desktop_style = edit_style | WINDOW_STYLE(ES_NUMBER as u32);
+        create_child(
+            dlg,
+            "EDIT",
+            "",
+            desktop_style,
+            (200, 259, 50, 24),
+            DESKTOP_ID,
+        );
+        let button_style = WS_TABSTOP | WINDOW_STYLE(BS_PUSHBUTTON as u32);
+        create_child(
+            dlg,
+            "BUTTON",
+            strings::text(Label::Update),
+            button_style,
+            (310, 258, 100, 26),
+            UPDATE_ID,
+        );
+
+        create_child(
+            dlg,
+            "BUTTON",
+            strings::text(Label::WrapAround),
+            check_style,
+            (10, 300, 400, 22),
+            WRAP_ID,
+        );
+        create_child(
+            dlg,
+            "BUTTON",
+            strings::text(Label::SkipEmpty),
+            check_style,
+            (10, 325, 400, 22),
+            SKIP_EMPTY_ID,
+        );
+        create_child(
+            dlg,
+            "BUTTON",
+            strings::text(Label::ConfirmDestructive),
+            check_style,
+            (10, 350, 400, 22),
+            CONFIRM_ID,
+        );
+        set_checked(dlg, WRAP_ID, config.wrap_around);
+        set_checked(dlg, SKIP_EMPTY_ID, config.skip_empty);
+        set_checked(dlg, CONFIRM_ID, config.confirm_destructive);
+
+        let ok_style = WS_TABSTOP | WINDOW_STYLE(BS_DEFPUSHBUTTON as u32);
+        create_child(
+            dlg,
+            "BUTTON",
+            strings::text(Label::Save),
+            ok_style,
+            (230, 385, 85, 26),
+            IDOK.0,
+        );
+        create_child(
+            dlg,
+            "BUTTON",
+            strings::text(Label::Cancel),
+            button_style,
+            (325, 385, 85, 26),
+            IDCANCEL.0,
+        );
+
+        fill_list(dlg, (!config.bindings.is_empty()).then_some(0));
+        load_selected(dlg);
+
+        SetThreadDpiAwarenessContext(dpi_context);
+        let _ = SetForegroundWindow(dlg);
+        let _ = SetFocus(list);
+
+        // Same nested loop as the prompt: IsDialogMessageW handles Tab, Enter, Esc.
+        let mut msg = MSG::default();
+        while !SETTINGS.with(|s| s.borrow().done) {
+            let res = GetMessageW(&mut msg, HWND::default(), 0, 0);
+            if res.0 <= 0 {
+                // WM_QUIT belongs to the outer loop.
+                PostQuitMessage(msg.wParam.0 as i32);
+                let _ = DestroyWindow(dlg);
+                return None;
+            }
+            if IsDialogMessageW(dlg, &msg).as_bool() {
+                continue;
+            }
+            let _ = TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+    }
+
+    let settings = SETTINGS.with(|s| s.take());
+    settings.saved.then_some(settings.config)
+}
@@ -0,0 +1,340 @@
+//! Tray menu, balloon and dialog text in the configured `language`. Labels missing
+//! from a translation fall back to English.
+
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Every translatable label. A `{}` in the text is filled in through [`format`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Label {
+    OneDesktop,
+    DesktopCount,
+    Desktop,
+    Desktops,
+    NewDesktop,
+    EnsureDesktops,
+    RemoveDesktop,
+    RenameDesktop,
+    MergeDesktops,
+    HotkeysEnabled,
+    FollowOnMove,
+    Settings,
+    ReloadConfig,
+    RunAtStartup,
+    About,
+    Exit,
+    CreateFailed,
+    RemoveOnlyDesktop,
+    RemoveFailed,
+    RenameFailed,
+    SaveFailed,
+    ReloadFailed,
+    RegisterFailed,
+    TaskbarShortcuts,
+    MissingDesktops,
+    ApiUnavailable,
+    WindowPinned,
+    WindowUnpinned,
+    WindowPinFailed,
+    NothingToPull,
+    AppNotFound,
+    AppPinned,
+    AppUnpinned,
+    AppPinFailed,
+    RemoveDesktopQuestion,
+    MergeDesktopsQuestion,
+    RenameDesktopTitle,
+    NamedDesktop,
+    Paused,
+    HotkeysTitle,
+    AboutText,
+    AboutApiWorking,
+    AboutApiFailed,
+    AboutUnknown,
+    Ok,
+    Cancel,
+    Yes,
+    No,
+    DontAskAgain,
+    ActionSwitch,
+    ActionMove,
+    ActionMoveFollow,
+    ActionNext,
+    ActionPrevious,
+    ActionMoveNext,
+    ActionFirst,
+    ActionLast,
+    ActionMoveFirst,
+    ActionMoveLast,
+    ActionMovePrevious,
+    ActionToggleLast,
+    ActionHistoryBack,
+    ActionHistoryForward,
+    ActionTogglePause,
+    ActionTogglePinWindow,
+    ActionTogglePinApp,
+    ActionMoveToNewDesktop,
+    ActionGotoNumber,
+    ActionPickDesktop,
+    ActionToggleFollow,
+    ActionShowDesktop,
+    ActionPullWindow,
+    ActionSwapNext,
+    ActionSwapPrevious,
+    ActionLaunch,
+    SettingsTitle,
+    UnknownKey,
+    DesktopNumberNeeded,
+    KeyField,
+    DesktopField,
+    Update,
+    WrapAround,
+    SkipEmpty,
+    ConfirmDestructive,
+    Save,
+    Crashed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Language {
+    English,
+    German,
+}
+
+static LANGUAGE: AtomicU8 = AtomicU8::new(Language::English as u8);
+
+/// Switch to the language with this code ("en", "de"); unknown codes mean English.
+pub fn set_language(code: &str) {
+    let language = match code.trim().to_ascii_lowercase().as_str() {
+        "de" => Language::German,
+        _ => Language::English,
+    };
+    LANGUAGE.store(language as u8, Ordering::Relaxed);
+}
+
+fn language() -> Language {
+    match LANGUAGE.load(Ordering::Relaxed) {
+        l if l == Language::German as u8 => Language::German,
+        _ => Language::English,
+    }
+}
+
+pub fn text(label: Label) -> &'static str {
+    let translated = match language() {
+        Language::English => None,
+        Language::German => german(label),
+    };
+    translated.unwrap_or_else(|| english(label))
+}
+
+/// The label with its `{}` replaced by `value`.
+pub fn format(label: Label, value: impl Display) -> String {
+    text(label).replacen("{}", &value.to_string(), 1)
+}
+
+/// The label with each `{}` replaced by the next of `values`, in order.
+pub fn format_all(label: Label, values: &[&dyn Display]) -> String {
+    values.iter().fold(text(label).to_string(), |text, value| {
+        text.replacen("{}", &value.to_string(), 1)
+    })
+}
+
+fn english(label: Label) -> &'static str {
+    match label {
+        Label::OneDesktop => "1 desktop",
+        Label::DesktopCount => "{} desktops",
+        Label::Desktop => "Desktop {}",
+        Label::Desktops => "Desktops",
+        Label::NewDesktop => "New desktop",
+        Label::EnsureDesktops => "Ensure {} desktops",
+        Label::RemoveDesktop => "Remove current desktop",
+        Label::RenameDesktop => "Rename desktop\u{2026}",
+        Label::MergeDesktops => "Merge all windows to desktop 1\u{2026}",
+        Label::HotkeysEnabled => "Hotkeys enabled",
+        Label::FollowOnMove => "Follow moved windows",
+        Label::Settings => "Settings\u{2026}",
+        Label::ReloadConfig => "Reload config",
+        Label::RunAtStartup => "Run at startup",
+        Label::About => "About d_switch",
+        Label::Exit => "Exit",
+        Label::CreateFailed => "Could not create a new desktop.",
+        Label::RemoveOnlyDesktop => "Can't remove the only desktop.",
+        Label::RemoveFailed => "Could not remove the desktop.",
+        Label::RenameFailed => "Could not rename the desktop.",
+        Label::SaveFailed => "Could not save the settings file.",
+        Label::ReloadFailed => "Config not reloaded: {}",
+        Label::RegisterFailed => "Could not register: {}",
+        Label::MissingDesktops => "No such desktop yet: {}",
+        Label::TaskbarShortcuts => {
+            "Win+number hotkeys replace launching taskbar apps. Set \
+             win_number_needs_shift to keep those."
+        }
+        Label::ApiUnavailable => {
+            "The Windows virtual desktop API is not responding, so switching \
+             doesn't work. A Windows update may have changed it; check for a \
+             newer d_switch."
+        }
+        Label::WindowPinned => "Window pinned",
+        Label::WindowUnpinned => "Window unpinned",
+        Label::WindowPinFailed => "Could not change the window's pin state.",
+        Label::NothingToPull => "No window on another desktop to bring here.",
+        Label::AppNotFound => "Could not find the window's app.",
+        Label::AppPinned => "App pinned",
+        Label::AppUnpinned => "App unpinned",
+        Label::AppPinFailed => "Could not change the app's pin state.",
+        Label::RemoveDesktopQuestion => {
+            "Remove desktop {}? Its windows move to an adjacent desktop."
+        }
+        Label::MergeDesktopsQuestion => "Move every window to desktop 1?",
+        Label::RenameDesktopTitle => "Rename desktop",
+        Label::NamedDesktop => "Desktop {}: {}",
+        Label::Paused => " (paused)",
+        Label::HotkeysTitle => "d_switch hotkeys",
+        Label::AboutText => "d_switch {}\n\nWindows build: {}\nVirtual desktop API: {}",
+        Label::AboutApiWorking => "working ({} desktops)",
+        Label::AboutApiFailed => "unavailable ({})",
+        Label::AboutUnknown => "unknown",
+        Label::Ok => "OK",
+        Label::Cancel => "Cancel",
+        Label::Yes => "Yes",
+        Label::No => "No",
+        Label::DontAskAgain => "Don't ask again",
+        Label::ActionSwitch => "Switch to desktop {}",
+        Label::ActionMove => "Move window to desktop {}",
+        Label::ActionMoveFollow => "Move window to desktop {} and follow",
+        Label::ActionNext => "Next desktop",
+        Label::ActionPrevious => "Previous desktop",
+        Label::ActionMoveNext => "Move window to next desktop",
+        Label::ActionFirst => "First desktop",
+        Label::ActionLast => "Go to last desktop",
+        Label::ActionMoveFirst => "Move window to first desktop",
+        Label::ActionMoveLast => "Move window to last desktop",
+        Label::ActionMovePrevious => "Move window to previous desktop",
+        Label::ActionToggleLast => "Previous desktop (toggle)",
+        Label::ActionHistoryBack => "Back in history",
+        Label::ActionHistoryForward => "Forward in history",
+        Label::ActionTogglePause => "Pause hotkeys",
+        Label::ActionTogglePinWindow => "Pin window",
+        Label::ActionTogglePinApp => "Pin app",
+        Label::ActionMoveToNewDesktop => "Move window to a new desktop",
+        Label::ActionGotoNumber => "Type a desktop number",
+        Label::ActionPickDesktop => "Pick a desktop from a grid",
+        Label::ActionToggleFollow => "Toggle following moved windows",
+        Label::ActionShowDesktop => "Show this desktop",
+        Label::ActionPullWindow => "Pull the last window here",
+        Label::ActionSwapNext => "Swap with the next desktop",
+        Label::ActionSwapPrevious => "Swap with the previous desktop",
+        Label::ActionLaunch => "Launch an app on desktop {}",
+        Label::SettingsTitle => "d_switch settings",
+        Label::UnknownKey => "Unknown key: \"{}\"",
+        Label::DesktopNumberNeeded => "Desktop must be a number from 1.",
+        Label::KeyField => "Key:",
+        Label::DesktopField => "Desktop:",
+        Label::Update => "Update",
+        Label::WrapAround => "Wrap around from the last desktop to the first",
+        Label::SkipEmpty => "Skip desktops without windows",
+        Label::ConfirmDestructive => "Ask before removing or merging desktops",
+        Label::Save => "Save",
+        Label::Crashed => "d_switch crashed",
+    }
+}
+
+fn german(label: Label) -> Option<&'static str> {
+    Some(match label {
+        Label::OneDesktop => "1 Desktop",
+        Label::DesktopCount => "{} Desktops",
+        Label::NewDesktop => "Neuer Desktop",
+        Label::EnsureDesktops => "Auf {} Desktops auff\u{fc}llen",
+        Label::RemoveDesktop => "Aktuellen Desktop entfernen",
+        Label::RenameDesktop => "Desktop umbenennen\u{2026}",
+        Label::MergeDesktops => "Alle Fenster auf Desktop 1 verschieben\u{2026}",
+        Label::HotkeysEnabled => "Tastenk\u{fc}rzel aktiv",
+        Label::FollowOnMove => "Verschobenen Fenstern folgen",
+        Label::Settings => "Einstellungen\u{2026}",
+        Label::ReloadConfig => "Konfiguration neu laden",
+        Label::RunAtStartup => "Mit Windows starten",
+        Label::About => "\u{dc}ber d_switch",
+        Label::Exit => "Beenden",
+        Label::CreateFailed => "Neuer Desktop konnte nicht erstellt werden.",
+        Label::RemoveOnlyDesktop => "Der einzige Desktop kann nicht entfernt werden.",
+        Label::RemoveFailed => "Desktop konnte nicht entfernt werden.",
+        Label::RenameFailed => "Desktop konnte nicht umbenannt werden.",
+        Label::SaveFailed => "Einstellungen konnten nicht gespeichert werden.",
+        Label::ReloadFailed => "Konfiguration nicht neu geladen: {}",
+        Label::RegisterFailed => "Nicht registriert: {}",
+        Label::MissingDesktops => "Desktop gibt es noch nicht: {}",
+        Label::TaskbarShortcuts => {
+            "Win+Zahl-Tastenk\u{fc}rzel ersetzen das Starten von Taskleisten-Apps. \
+             Mit win_number_needs_shift bleiben diese erhalten."
+        }
+        Label::ApiUnavailable => {
+            "Die Windows-API f\u{fc}r virtuelle Desktops antwortet nicht, daher \
+             funktioniert das Wechseln nicht. Ein Windows-Update hat sie \
+             vielleicht ge\u{e4}ndert; gibt es ein neueres d_switch?"
+        }
+        Label::WindowPinned => "Fenster angeheftet",
+        Label::WindowUnpinned => "Fenster gel\u{f6}st",
+        Label::WindowPinFailed => "Fenster konnte nicht an- oder abgeheftet werden.",
+        Label::NothingToPull => "Kein Fenster auf einem anderen Desktop zum Herholen.",
+        Label::AppNotFound => "App des Fensters nicht gefunden.",
+        Label::AppPinned => "App angeheftet",
+        Label::AppUnpinned => "App gel\u{f6}st",
+        Label::AppPinFailed => "App konnte nicht an- oder abgeheftet werden.",
+        Label::RemoveDesktopQuestion => {
+            "Desktop {} entfernen? Seine Fenster kommen auf einen benachbarten Desktop."
+        }
+        Label::MergeDesktopsQuestion => "Alle Fenster auf Desktop 1 verschieben?",
+        Label::RenameDesktopTitle => "Desktop umbenennen",
+        Label::Paused => " (pausiert)",
+        Label::HotkeysTitle => "d_switch-Tastenk\u{fc}rzel",
+        Label::AboutText => "d_switch {}\n\nWindows-Build: {}\nAPI f\u{fc}r virtuelle Desktops: {}",
+        Label::AboutApiWorking => "funktioniert ({} Desktops)",
+        Label::AboutApiFailed => "nicht verf\u{fc}gbar ({})",
+        Label::AboutUnknown => "unbekannt",
+        Label::Cancel => "Abbrechen",
+        Label::Yes => "Ja",
+        Label::No => "Nein",
+        Label::DontAskAgain => "Nicht mehr fragen",
+        Label::Desktop => "Desktop {}",
+        Label::Desktops => "Desktops",
+        Label::NamedDesktop => "Desktop {}: {}",
+        Label::Ok => "OK",
+        Label::ActionSwitch => "Zu Desktop {} wechseln",
+        Label::ActionMove => "Fenster auf Desktop {} verschieben",
+        Label::ActionMoveFollow => "Fenster auf Desktop {} verschieben und folgen",
+        Label::ActionNext => "N\u{e4}chster Desktop",
+        Label::ActionPrevious => "Vorheriger Desktop",
+        Label::ActionMoveNext => "Fenster auf den n\u{e4}chsten Desktop verschieben",
+        Label::ActionFirst => "Erster Desktop",
+        Label::ActionLast => "Zum letzten Desktop",
+        Label::ActionMoveFirst => "Fenster auf den ersten Desktop verschieben",
+        Label::ActionMoveLast => "Fenster auf den letzten Desktop verschieben",
+        Label::ActionMovePrevious => "Fenster auf den vorherigen Desktop verschieben",
+        Label::ActionToggleLast => "Vorheriger Desktop (hin und her)",
+        Label::ActionHistoryBack => "Im Verlauf zur\u{fc}ck",
+        Label::ActionHistoryForward => "Im Verlauf vorw\u{e4}rts",
+        Label::ActionTogglePause => "Tastenk\u{fc}rzel pausieren",
+        Label::ActionTogglePinWindow => "Fenster anheften",
+        Label::ActionTogglePinApp => "App anheften",
+        Label::ActionMoveToNewDesktop => "Fenster auf einen neuen Desktop verschieben",
+        Label::ActionGotoNumber => "Desktop-Nummer eingeben",
+        Label::ActionPickDesktop => "Desktop aus einem Raster w\u{e4}hlen",
+        Label::ActionToggleFollow => "Verschobenen Fenstern folgen an/aus",
+        Label::ActionShowDesktop => "Diesen Desktop anzeigen",
+        Label::ActionPullWindow => "Letztes Fenster hierher holen",
+        Label::ActionSwapNext => "Mit dem n\u{e4}chsten Desktop tauschen",
+        Label::ActionSwapPrevious => "Mit dem vorherigen Desktop tauschen",
+        Label::ActionLaunch => "App auf Desktop {} starten",
+        Label::SettingsTitle => "d_switch-Einstellungen",
+        Label::UnknownKey => "Unbekannte Taste: \"{}\"",
+        Label::DesktopNumberNeeded => "Der Desktop muss eine Zahl ab 1 sein.",
+        Label::KeyField => "Taste:",
+        Label::DesktopField => "Desktop:",
+        Label::Update => "\u{dc}bernehmen",
+        Label::WrapAround => "Vom letzten Desktop wieder zum ersten",
+        Label::SkipEmpty => "Desktops ohne Fenster \u{fc}berspringen",
+        Label::ConfirmDestructive => "Vor dem Entfernen oder Zusammenlegen von Desktops fragen",
+        Label::Save => "Speichern",
+        Label::Crashed => "d_switch ist abgest\u{fc}rzt",
+    })
+}
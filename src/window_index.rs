@@ -0,0 +1,90 @@
+//! Which windows are on which desktop, from one `EnumWindows` pass.
+//!
+//! Asking winvd for every window's desktop is slow, and skip-empty navigation,
+//! merging and the desktop default app all want the same answer, so it is
+//! built once and kept for a moment. The desktop listener drops it on every
+//! winvd event, and moves made from here drop it too.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use windows::Win32::Foundation::HWND;
+use windows::Win32::UI::WindowsAndMessaging::IsWindowVisible;
+
+use crate::app::top_level_windows;
+
+/// How long a built index is trusted without an invalidating event.
+const TTL: Duration = Duration::from_millis(500);
+
+/// Visible, unpinned top-level windows by desktop index, front to back.
+/// Raw handle values, so the index can sit in a static shared with the
+/// switch worker.
+pub struct DesktopWindowIndex {
+    built: Option<Instant>,
+    windows: HashMap<u32, Vec<usize>>,
+}
+
+impl DesktopWindowIndex {
+    /// Forget the windows, so the next query enumerates them again.
+    pub fn invalidate(&mut self) {
+        self.built = None;
+    }
+
+    /// The windows on `desktop`, front to back.
+    pub fn windows_on(&mut self, desktop: u32) -> Vec<HWND> {
+        self.refresh();
+        self.windows
+            .get(&desktop)
+            .map(|raw| {
+                raw.iter()
+                    .map(|&w| HWND(w as *mut core::ffi::c_void))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Whether `desktop` has any window on it.
+    pub fn is_occupied(&mut self, desktop: u32) -> bool {
+        self.refresh();
+        self.windows.get(&desktop).is_some_and(|w| !w.is_empty())
+    }
+
+    fn refresh(&mut self) {
+        if self.built.is_some_and(|at| at.elapsed() < TTL) {
+            return;
+        }
+        self.windows.clear();
+        for hwnd in unsafe { top_level_windows() } {
+            if !unsafe { IsWindowVisible(hwnd) }.as_bool()
+                || winvd::is_pinned_window(hwnd).unwrap_or(false)
+            {
+                continue;
+            }
+            if let Ok(index) = winvd::get_desktop_by_window(hwnd).and_then(|d| d.get_index()) {
+                self.windows.entry(index).or_default().push(hwnd.0 as usize);
+            }
+        }
+        self.built = Some(Instant::now());
+    }
+}
+
+static INDEX: OnceLock<Mutex<DesktopWindowIndex>> = OnceLock::new();
+
+/// The shared index. Hold the lock only for a query; the UI thread and the
+/// switch worker both use it.
+pub fn window_index() -> &'static Mutex<DesktopWindowIndex> {
+    INDEX.get_or_init(|| {
+        Mutex::new(DesktopWindowIndex {
+            built: None,
+            windows: HashMap::new(),
+        })
+    })
+}
+
+/// Drop the shared index, e.g. after moving windows between desktops.
+pub fn invalidate() {
+    if let Ok(mut index) = window_index().lock() {
+        index.invalidate();
+    }
+}